@@ -0,0 +1,282 @@
+use crate::error::{Error, ErrorCode};
+use crate::token::{Token, TokenType::*};
+use crate::typer::{Typer, DIVISION_DIGITS};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, Zero};
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+/// Numeric operands after promotion. Two ints stay ints and anything mixed
+/// with a float becomes two floats. Once an exact number is involved the
+/// result stays exact: big ints with ints, decimals with everything else.
+enum Numbers {
+    Ints(i64, i64),
+    Floats(f64, f64),
+    BigInts(BigInt, BigInt),
+    Decimals(BigDecimal, BigDecimal),
+}
+
+fn numbers(left: &Typer, right: &Typer) -> Option<Numbers> {
+    match (left, right) {
+        (Typer::Int(left), Typer::Int(right)) => Some(Numbers::Ints(*left, *right)),
+        (Typer::Int(_), Typer::Number(_))
+        | (Typer::Number(_), Typer::Int(_))
+        | (Typer::Number(_), Typer::Number(_)) => {
+            Some(Numbers::Floats(left.as_number()?, right.as_number()?))
+        }
+        _ => match (big_int(left), big_int(right)) {
+            (Some(left), Some(right)) => Some(Numbers::BigInts(left, right)),
+            _ => match (decimal(left), decimal(right)) {
+                (Some(left), Some(right)) => Some(Numbers::Decimals(left, right)),
+                // a NaN or infinite float has no exact form
+                _ => Some(Numbers::Floats(left.as_number()?, right.as_number()?)),
+            },
+        },
+    }
+}
+
+impl Numbers {
+    fn ordering(&self) -> Option<Ordering> {
+        match self {
+            Numbers::Ints(left, right) => left.partial_cmp(right),
+            Numbers::Floats(left, right) => left.partial_cmp(right),
+            Numbers::BigInts(left, right) => left.partial_cmp(right),
+            Numbers::Decimals(left, right) => left.partial_cmp(right),
+        }
+    }
+
+    fn divisor_is_zero(&self) -> bool {
+        match self {
+            Numbers::Ints(_, right) => *right == 0,
+            Numbers::Floats(_, right) => *right == 0.0,
+            Numbers::BigInts(_, right) => right.is_zero(),
+            Numbers::Decimals(_, right) => right.is_zero(),
+        }
+    }
+}
+
+fn big_int(value: &Typer) -> Option<BigInt> {
+    match value {
+        Typer::Int(num) => Some(BigInt::from(*num)),
+        Typer::BigInt(num) => Some((**num).clone()),
+        _ => None,
+    }
+}
+
+fn decimal(value: &Typer) -> Option<BigDecimal> {
+    match value {
+        Typer::Int(num) => Some(BigDecimal::from(*num)),
+        Typer::BigInt(num) => Some(BigDecimal::from((**num).clone())),
+        Typer::Decimal(num) => Some((**num).clone()),
+        // the float as it prints, so `0.1` becomes exactly 0.1
+        Typer::Number(num) if num.is_finite() => num.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// `left / right` for decimals, rounded to `DIVISION_DIGITS` significant
+/// digits when it needs more, as 1/3 does.
+fn quotient(left: &BigDecimal, right: &BigDecimal) -> BigDecimal {
+    let quotient = left / right;
+    if quotient.digits() > DIVISION_DIGITS {
+        quotient.with_prec(DIVISION_DIGITS)
+    } else {
+        quotient
+    }
+}
+
+/// The quotient of two decimals truncated toward zero, computed on the
+/// unscaled digits so it stays exact however long the operands are.
+fn truncated_quotient(left: BigDecimal, right: BigDecimal) -> BigDecimal {
+    let scale = left
+        .fractional_digit_count()
+        .max(right.fractional_digit_count());
+    let (left, _) = left.with_scale(scale).into_bigint_and_exponent();
+    let (right, _) = right.with_scale(scale).into_bigint_and_exponent();
+    BigDecimal::from(left / right)
+}
+
+fn error(operator: &Token, code: ErrorCode, message: &str) -> Error {
+    Error::RuntimeError {
+        token: Some(operator.clone()),
+        code,
+        message: String::from(message),
+        trace: Vec::new(),
+    }
+}
+
+fn overflow(operator: &Token) -> Error {
+    error(
+        operator,
+        ErrorCode::IntegerOverflow,
+        &format!("Integer overflow in '{}'.", operator.lexeme),
+    )
+}
+
+fn checked(result: Option<i64>, operator: &Token) -> Result<Typer, Error> {
+    result.map(Typer::Int).ok_or_else(|| overflow(operator))
+}
+
+fn divide_by_zero(operator: &Token) -> Error {
+    error(
+        operator,
+        ErrorCode::DivisionByZero,
+        "Divide by zero error. The denominator is equal to zero!",
+    )
+}
+
+/// Applies a binary arithmetic or comparison operator. Integer results that
+/// don't fit in an i64 are errors rather than wrapping around.
+pub fn binary(operator: &Token, left: Typer, right: Typer) -> Result<Typer, Error> {
+    let mismatch = |what: &str| {
+        error(
+            operator,
+            ErrorCode::TypeMismatch,
+            &format!("Mismatched types. Cannot {} the two operands", what),
+        )
+    };
+    let numbers = numbers(&left, &right);
+    if matches!(operator.token_type, SLASH | TILDE_SLASH | PERCENT)
+        && numbers.as_ref().is_some_and(Numbers::divisor_is_zero)
+    {
+        return Err(divide_by_zero(operator));
+    }
+
+    match operator.token_type {
+        PLUS => match (numbers, left, right) {
+            (Some(Numbers::Ints(left, right)), ..) => checked(left.checked_add(right), operator),
+            (Some(Numbers::Floats(left, right)), ..) => Ok(Typer::Number(left + right)),
+            (Some(Numbers::BigInts(left, right)), ..) => Ok(Typer::from(left + right)),
+            (Some(Numbers::Decimals(left, right)), ..) => Ok(Typer::from(left + right)),
+            (None, Typer::Str(left), Typer::Str(right)) => {
+                Ok(Typer::from(format!("{}{}", left, right)))
+            }
+            (None, Typer::Str(left), right @ Typer::Int(_))
+            | (None, Typer::Str(left), right @ Typer::Number(_))
+            | (None, Typer::Str(left), right @ Typer::BigInt(_))
+            | (None, Typer::Str(left), right @ Typer::Decimal(_))
+            | (None, Typer::Str(left), right @ Typer::Boolean(_)) => {
+                Ok(Typer::from(format!("{}{}", left, right)))
+            }
+            _ => Err(mismatch("add")),
+        },
+        MINUS => match numbers {
+            Some(Numbers::Ints(left, right)) => checked(left.checked_sub(right), operator),
+            Some(Numbers::Floats(left, right)) => Ok(Typer::Number(left - right)),
+            Some(Numbers::BigInts(left, right)) => Ok(Typer::from(left - right)),
+            Some(Numbers::Decimals(left, right)) => Ok(Typer::from(left - right)),
+            None => Err(mismatch("subtract")),
+        },
+        STAR => match numbers {
+            Some(Numbers::Ints(left, right)) => checked(left.checked_mul(right), operator),
+            Some(Numbers::Floats(left, right)) => Ok(Typer::Number(left * right)),
+            Some(Numbers::BigInts(left, right)) => Ok(Typer::from(left * right)),
+            Some(Numbers::Decimals(left, right)) => Ok(Typer::from(left * right)),
+            None => Err(mismatch("multiply")),
+        },
+        // `/` always divides exactly, so `7 / 2` is 3.5 even for ints
+        SLASH => match numbers {
+            Some(Numbers::Ints(left, right)) => Ok(Typer::Number(left as f64 / right as f64)),
+            Some(Numbers::Floats(left, right)) => Ok(Typer::Number(left / right)),
+            Some(Numbers::BigInts(left, right)) => Ok(Typer::from(quotient(
+                &BigDecimal::from(left),
+                &BigDecimal::from(right),
+            ))),
+            Some(Numbers::Decimals(left, right)) => Ok(Typer::from(quotient(&left, &right))),
+            None => Err(mismatch("divide")),
+        },
+        // integer division truncates toward zero
+        TILDE_SLASH => match numbers {
+            Some(Numbers::Ints(left, right)) => checked(left.checked_div(right), operator),
+            Some(Numbers::Floats(left, right)) => Ok(Typer::Number((left / right).trunc())),
+            Some(Numbers::BigInts(left, right)) => Ok(Typer::from(left / right)),
+            Some(Numbers::Decimals(left, right)) => {
+                Ok(Typer::from(truncated_quotient(left, right)))
+            }
+            None => Err(mismatch("divide")),
+        },
+        // the remainder takes the sign of the left operand
+        PERCENT => match numbers {
+            // the divisor isn't zero, and `i64::MIN % -1` is 0 even though
+            // `i64::MIN / -1` overflows
+            Some(Numbers::Ints(left, right)) => Ok(Typer::Int(left.wrapping_rem(right))),
+            Some(Numbers::Floats(left, right)) => Ok(Typer::Number(left % right)),
+            Some(Numbers::BigInts(left, right)) => Ok(Typer::from(left % right)),
+            Some(Numbers::Decimals(left, right)) => Ok(Typer::from(left % right)),
+            None => Err(mismatch("divide")),
+        },
+        GREATER | GREATER_EQUAL | LESS | LESS_EQUAL => {
+            let ordering = match (numbers, &left, &right) {
+                (Some(numbers), ..) => numbers.ordering(),
+                (None, Typer::Str(left), Typer::Str(right)) => left.partial_cmp(right),
+                _ => return Err(mismatch("compare")),
+            };
+            // NaN is unordered, so every comparison with it is false
+            Ok(Typer::Boolean(ordering.is_some_and(
+                |ordering| match operator.token_type {
+                    GREATER => ordering.is_gt(),
+                    GREATER_EQUAL => ordering.is_ge(),
+                    LESS => ordering.is_lt(),
+                    _ => ordering.is_le(),
+                },
+            )))
+        }
+        BANG_EQUAL => Ok(Typer::Boolean(!equal(&left, &right, operator)?)),
+        EQUAL_EQUAL => Ok(Typer::Boolean(equal(&left, &right, operator)?)),
+        _ => Err(error(
+            operator,
+            ErrorCode::UnknownOperator,
+            "Cannot fathom the binary operation. Check your code again",
+        )),
+    }
+}
+
+/// Orders two numbers of any kind by value; `None` if either isn't a
+/// number or one is NaN.
+pub fn compare(left: &Typer, right: &Typer) -> Option<Ordering> {
+    numbers(left, right)?.ordering()
+}
+
+pub fn negate(operator: &Token, operand: Typer) -> Result<Typer, Error> {
+    match operand {
+        Typer::Int(num) => checked(num.checked_neg(), operator),
+        Typer::Number(num) => Ok(Typer::Number(-num)),
+        Typer::BigInt(num) => Ok(Typer::from(-&*num)),
+        Typer::Decimal(num) => Ok(Typer::from(-&*num)),
+        _ => Err(error(
+            operator,
+            ErrorCode::TypeMismatch,
+            "Mismatched unary operation. Cannot perform operation on the following.",
+        )),
+    }
+}
+
+/// Numbers compare by value whatever their kind, so `1 == 1.0`; lists, maps, functions,
+/// classes and instances compare by identity.
+pub fn equal(left: &Typer, right: &Typer, operator: &Token) -> Result<bool, Error> {
+    if let Some(numbers) = numbers(left, right) {
+        return Ok(match numbers {
+            Numbers::Ints(left, right) => left == right,
+            Numbers::Floats(left, right) => left == right,
+            Numbers::BigInts(left, right) => left == right,
+            Numbers::Decimals(left, right) => left == right,
+        });
+    }
+    match (left, right) {
+        (Typer::Nil, Typer::Nil) => Ok(true),
+        (Typer::Nil, _) => Ok(false),
+        // strings are interned, so this compares handles
+        (Typer::Str(left), Typer::Str(right)) => Ok(left == right),
+        (Typer::Boolean(left), Typer::Boolean(right)) => Ok(left == right),
+        (Typer::Callable(left), Typer::Callable(right)) => Ok(Rc::ptr_eq(left, right)),
+        (Typer::Class(left), Typer::Class(right)) => Ok(Rc::ptr_eq(left, right)),
+        (Typer::Instance(left), Typer::Instance(right)) => Ok(Rc::ptr_eq(left, right)),
+        (Typer::List(left), Typer::List(right)) => Ok(Rc::ptr_eq(left, right)),
+        (Typer::Map(left), Typer::Map(right)) => Ok(Rc::ptr_eq(left, right)),
+        (_, _) => Err(error(
+            operator,
+            ErrorCode::TypeMismatch,
+            "Mismatched types, cannot compare the two operands",
+        )),
+    }
+}
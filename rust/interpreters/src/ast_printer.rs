@@ -31,8 +31,57 @@ impl RPN for Expr {
             Expr::Binary(left_expr, ops, right_expr) => {
                 parenthesize_rpn!(&ops.lexeme, &left_expr, &right_expr)
             }
-            Expr::Grouping(expr) => parenthesize_rpn!(&String::from(""), &expr),
-            Expr::LiteralExpr(literal) => match literal {
+            Expr::Call(callee, _paren, arguments) => {
+                let mut s = callee.print_rpn();
+                for argument in arguments {
+                    s.push(' ');
+                    s.push_str(&argument.print_rpn());
+                }
+                s.push_str(" call");
+                s
+            }
+            Expr::Get(object, name) => format!("{} {} .", object.print_rpn(), name.lexeme),
+            Expr::Set(object, name, value) => format!(
+                "{} {} {} =",
+                object.print_rpn(),
+                name.lexeme,
+                value.print_rpn()
+            ),
+            Expr::Super(keyword, method, _depth) => {
+                format!("{} {} .", keyword.lexeme, method.lexeme)
+            }
+            Expr::This(keyword, _depth) => keyword.lexeme.to_string(),
+            Expr::Grouping(expr, _span) => parenthesize_rpn!(&String::from(""), &expr),
+            Expr::Index(object, _bracket, index) => {
+                format!("{} {} []", object.print_rpn(), index.print_rpn())
+            }
+            Expr::IndexSet(object, _bracket, index, value) => format!(
+                "{} {} {} []=",
+                object.print_rpn(),
+                index.print_rpn(),
+                value.print_rpn()
+            ),
+            Expr::List(elements, _span) => {
+                let mut s = String::new();
+                for element in elements {
+                    s.push_str(&element.print_rpn());
+                    s.push(' ');
+                }
+                s.push_str(&format!("{} list", elements.len()));
+                s
+            }
+            Expr::Logical(left, operator, right) => {
+                parenthesize_rpn!(&operator.lexeme, &left, &right)
+            }
+            Expr::Map(entries, _span) => {
+                let mut s = String::new();
+                for (key, _colon, value) in entries {
+                    s.push_str(&format!("{} {} ", key.print_rpn(), value.print_rpn()));
+                }
+                s.push_str(&format!("{} map", entries.len()));
+                s
+            }
+            Expr::LiteralExpr(literal, _span) => match literal {
                 Some(lit) => lit.to_string(),
                 None => String::from("None"),
             },
@@ -40,10 +89,10 @@ impl RPN for Expr {
             Expr::Ternary(condition, first, second, _line) => {
                 parenthesize_rpn!("ternary", condition, first, second)
             }
-            Expr::Assign { name, value } => {
-                format!("{} = {}", name.to_string(), parenthesize_rpn!("", &value))
+            Expr::Assign { name, value, .. } => {
+                format!("{} = {}", name, parenthesize_rpn!("", &value))
             }
-            Expr::Variable(name) => name.to_string(),
+            Expr::Variable(name, _depth) => name.to_string(),
         }
     }
 }
@@ -51,18 +100,24 @@ impl RPN for Expr {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::expr::Expr::{Binary, Grouping, LiteralExpr, Unary};
-    use crate::token::{Literal::Number, Token, TokenType};
+    use crate::expr::Expr::{Binary, Grouping, LiteralExpr, Logical, Unary};
+    use crate::token::{
+        Literal::{Bool, Nil, Number},
+        Span, Token, TokenType,
+    };
 
     #[test]
     fn ast_printer_basics() {
         let expression = Binary(
             Box::new(Unary(
                 Token::new(TokenType::MINUS, "-", None, 1),
-                Box::new(LiteralExpr(Some(Number(123 as f64)))),
+                Box::new(LiteralExpr(Some(Number(123.0)), Span::default())),
             )),
             Token::new(TokenType::STAR, "*", None, 1),
-            Box::new(Grouping(Box::new(LiteralExpr(Some(Number(45.67)))))),
+            Box::new(Grouping(
+                Box::new(LiteralExpr(Some(Number(45.67)), Span::default())),
+                Span::default(),
+            )),
         );
 
         assert_eq!("(* (- 123) (group 45.67))", expression.print());
@@ -75,18 +130,34 @@ mod test {
         let tok_minus = Token::new(TokenType::MINUS, "-", None, 1);
         let expression = Binary(
             Box::new(Binary(
-                Box::new(LiteralExpr(Some(Number(1 as f64)))),
+                Box::new(LiteralExpr(Some(Number(1.0)), Span::default())),
                 tok_plus,
-                Box::new(LiteralExpr(Some(Number(2 as f64)))),
+                Box::new(LiteralExpr(Some(Number(2.0)), Span::default())),
             )),
             tok_star,
             Box::new(Binary(
-                Box::new(LiteralExpr(Some(Number(4 as f64)))),
+                Box::new(LiteralExpr(Some(Number(4.0)), Span::default())),
                 tok_minus,
-                Box::new(LiteralExpr(Some(Number(3 as f64)))),
+                Box::new(LiteralExpr(Some(Number(3.0)), Span::default())),
             )),
         );
 
         assert_eq!("1 2 + 4 3 - *", expression.print_rpn());
     }
+
+    #[test]
+    fn ast_printer_logical() {
+        let expression = Logical(
+            Box::new(LiteralExpr(Some(Bool(true)), Span::default())),
+            Token::new(TokenType::OR, "or", None, 1),
+            Box::new(Logical(
+                Box::new(LiteralExpr(Some(Nil), Span::default())),
+                Token::new(TokenType::AND, "and", None, 1),
+                Box::new(LiteralExpr(Some(Number(2.0)), Span::default())),
+            )),
+        );
+
+        assert_eq!("(or true (and Nil 2))", expression.print());
+        assert_eq!("true Nil 2 and or", expression.print_rpn());
+    }
 }
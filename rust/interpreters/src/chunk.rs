@@ -0,0 +1,244 @@
+use crate::interner::Symbol;
+use crate::token::{Span, Token};
+use crate::typer::Typer;
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::BigDecimal;
+use std::rc::Rc;
+
+/// One VM instruction. Operands follow the opcode byte; two-byte operands
+/// are big-endian. A "site" operand indexes `Chunk::sites` and says where in
+/// the source the instruction came from, for errors and call traces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpCode {
+    Constant, // constant (u16)
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,     // slot (u8)
+    SetLocal,     // slot (u8)
+    DefineGlobal, // name constant (u16)
+    GetGlobal,    // site of the name (u16)
+    SetGlobal,    // site of the name (u16)
+    GetUpvalue,   // upvalue (u8)
+    SetUpvalue,   // upvalue (u8)
+    GetProperty,  // site of the name (u16)
+    SetProperty,  // site of the name (u16)
+    GetSuper,     // site of the method name (u16)
+    GetIndex,     // site of the bracket (u16)
+    SetIndex,     // site of the bracket (u16)
+    Equal,        // site of the operator (u16), as for every operator below
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Modulo,
+    Negate,
+    Not,
+    Ternary,      // site of the operator (u16)
+    Print,        // site of the keyword (u16)
+    Jump,         // forward offset (u16)
+    JumpIfFalse,  // forward offset (u16), leaves the condition on the stack
+    Loop,         // backward offset (u16)
+    Call,         // argument count (u8), site of the closing paren (u16)
+    Closure,      // function constant (u16), then is_local (u8) and index (u8) per upvalue
+    CloseUpvalue, // moves the top of the stack into the upvalue pointing at it
+    Return,
+    Class, // site of the name (u16), method count (u8), has superclass (u8)
+    List,  // item count (u16)
+    Map,
+    MapEntry, // site of the colon (u16)
+}
+
+impl OpCode {
+    const ALL: [OpCode; 44] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::DefineGlobal,
+        OpCode::GetGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::GetIndex,
+        OpCode::SetIndex,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::IntDivide,
+        OpCode::Modulo,
+        OpCode::Negate,
+        OpCode::Not,
+        OpCode::Ternary,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::List,
+        OpCode::Map,
+        OpCode::MapEntry,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OpCode::ALL.get(byte as usize).copied()
+    }
+
+    /// Number of operand bytes after the opcode. `Closure` is followed by
+    /// two more for each upvalue of the function it creates.
+    pub fn operand_len(self) -> usize {
+        use OpCode::*;
+        match self {
+            Nil | True | False | Pop | Not | CloseUpvalue | Return | Map => 0,
+            GetLocal | SetLocal | GetUpvalue | SetUpvalue => 1,
+            Call => 3,
+            Class => 4,
+            _ => 2,
+        }
+    }
+}
+
+/// A value known at compile time. Functions are stored here too, as the
+/// prototypes `OpCode::Closure` instantiates.
+#[derive(Debug)]
+pub enum Constant {
+    Int(i64),
+    Number(f64),
+    BigInt(Rc<BigInt>),
+    Decimal(Rc<BigDecimal>),
+    Str(Symbol),
+    Function(Rc<Function>),
+}
+
+impl Constant {
+    /// The runtime value of a constant, or `None` for a function prototype.
+    pub fn value(&self) -> Option<Typer> {
+        match self {
+            Constant::Int(num) => Some(Typer::Int(*num)),
+            Constant::Number(num) => Some(Typer::Number(*num)),
+            Constant::BigInt(num) => Some(Typer::BigInt(Rc::clone(num))),
+            Constant::Decimal(num) => Some(Typer::Decimal(Rc::clone(num))),
+            Constant::Str(string) => Some(Typer::Str(string.clone())),
+            Constant::Function(_) => None,
+        }
+    }
+}
+
+/// Where an instruction came from: the token its errors point at and the
+/// whole expression, which for calls is the call site shown in traces.
+#[derive(Debug, Clone)]
+pub struct Site {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// A compiled function body: its code and the tables the code refers to.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    pub sites: Vec<Site>,
+    // (offset, line) for each run of bytes compiled from the same line
+    pub(crate) lines: Vec<(usize, usize)>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, line: usize) {
+        if self.lines.last().map(|&(_, last)| last) != Some(line) {
+            self.lines.push((self.code.len(), line));
+        }
+        self.code.push(byte);
+    }
+
+    /// Source line of the byte at `offset`.
+    pub fn line(&self, offset: usize) -> usize {
+        let run = self.lines.partition_point(|&(start, _)| start <= offset);
+        run.checked_sub(1).map_or(0, |run| self.lines[run].1)
+    }
+
+    /// Adds `constant` to the pool, reusing an existing entry for a string
+    /// that is already there, and returns its index.
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        if let Constant::Str(string) = &constant {
+            let existing = self
+                .constants
+                .iter()
+                .position(|c| matches!(c, Constant::Str(s) if s == string));
+            if let Some(index) = existing {
+                return index;
+            }
+        }
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    pub fn add_site(&mut self, token: &Token, span: Span) -> usize {
+        self.sites.push(Site {
+            token: token.clone(),
+            span,
+        });
+        self.sites.len() - 1
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+}
+
+/// A function compiled to bytecode; the top-level script is one too.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub upvalues: usize,
+    pub chunk: Chunk,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_table() {
+        let mut chunk = Chunk::default();
+        for (byte, line) in [(0, 1), (1, 1), (2, 3), (3, 3), (4, 4)] {
+            chunk.write(byte, line);
+        }
+        let lines: Vec<_> = (0..5).map(|offset| chunk.line(offset)).collect();
+        assert_eq!(lines, vec![1, 1, 3, 3, 4]);
+        assert_eq!(chunk.lines.len(), 3, "runs of one line share an entry");
+    }
+
+    #[test]
+    fn opcodes_round_trip() {
+        for (byte, op) in OpCode::ALL.iter().enumerate() {
+            assert_eq!(*op as u8 as usize, byte);
+            assert_eq!(OpCode::from_byte(byte as u8), Some(*op));
+        }
+        assert_eq!(OpCode::from_byte(OpCode::ALL.len() as u8), None);
+    }
+}
@@ -0,0 +1,652 @@
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+use crate::error::{Error, ErrorCode};
+use crate::expr::Expr;
+use crate::interner::{intern, Symbol};
+use crate::lox_function::FunctionKind;
+use crate::stmt::Stmt;
+use crate::token::{Literal, Span, Token, TokenType::*};
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+/// A local variable living in a stack slot of the function being compiled.
+struct Local {
+    name: Symbol,
+    depth: usize,
+    // a closure refers to it, so it must outlive its slot
+    captured: bool,
+}
+
+/// Where a closure finds a variable from an enclosing function: a slot of
+/// the function right around it, or one of that function's own upvalues.
+#[derive(Clone, Copy, PartialEq)]
+struct Upvalue {
+    index: u8,
+    is_local: bool,
+}
+
+struct FunctionState {
+    name: String,
+    arity: usize,
+    kind: FunctionKind,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: &str, arity: usize, kind: FunctionKind) -> Self {
+        // slot 0 holds the function being called, or the instance for methods
+        let receiver = match kind {
+            FunctionKind::Function => "",
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+        };
+        Self {
+            name: String::from(name),
+            arity,
+            kind,
+            chunk: Chunk::default(),
+            locals: vec![Local {
+                name: intern(receiver),
+                depth: 0,
+                captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        }
+    }
+}
+
+fn too_large(token: Option<&Token>, message: &str) -> Error {
+    Error::CompileTimeError {
+        token: token.cloned(),
+        code: ErrorCode::ChunkTooLarge,
+        message: String::from(message),
+    }
+}
+
+/// Compiles resolved statements to bytecode for the VM. Scoping follows the
+/// resolver: anything not found in an enclosing scope is a global.
+pub struct Compiler {
+    // the function being compiled is last, the functions around it before it
+    functions: Vec<FunctionState>,
+    // line of the code being compiled, recorded in the chunk's line table
+    line: usize,
+}
+
+impl Compiler {
+    fn new() -> Self {
+        Self {
+            functions: vec![FunctionState::new("<script>", 0, FunctionKind::Function)],
+            line: 1,
+        }
+    }
+
+    /// Compiles a whole script into a function that takes no arguments.
+    pub fn compile(statements: &[Stmt]) -> Result<Rc<Function>, Error> {
+        let mut compiler = Compiler::new();
+        for statement in statements {
+            compiler.statement(statement)?;
+        }
+        compiler.emit_return();
+        Ok(Rc::new(compiler.finish().0))
+    }
+
+    /// Compiles a single expression into a function that returns its value.
+    pub fn compile_expression(expr: &Expr) -> Result<Rc<Function>, Error> {
+        let mut compiler = Compiler::new();
+        compiler.expression(expr)?;
+        compiler.emit_op(OpCode::Return);
+        Ok(Rc::new(compiler.finish().0))
+    }
+
+    fn finish(&mut self) -> (Function, Vec<Upvalue>) {
+        let state = self.functions.pop().expect("a function is being compiled");
+        let function = Function {
+            name: state.name,
+            arity: state.arity,
+            upvalues: state.upvalues.len(),
+            chunk: state.chunk,
+        };
+        (function, state.upvalues)
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("a function is being compiled")
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<(), Error> {
+        self.line = statement.span().line;
+        match statement {
+            Stmt::Block(statements, _span) => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                self.end_scope();
+            }
+            Stmt::Class(name, superclass, methods) => {
+                self.class(name, superclass.as_ref(), methods)?
+            }
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Function(name, params, body) => {
+                let global = self.declare(name)?;
+                self.function(name, params, body, FunctionKind::Function)?;
+                self.define(global, name)?;
+            }
+            Stmt::If(condition, then_branch, else_branch, _keyword) => {
+                self.expression(condition)?;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch)?;
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump)?;
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(else_jump)?;
+            }
+            Stmt::Print(expr, keyword) => {
+                self.expression(expr)?;
+                let print = Token::with_span(PRINT, "print", None, *keyword);
+                self.emit_site(OpCode::Print, &print, *keyword)?;
+            }
+            Stmt::Return(_keyword, value) => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.emit_default_result(),
+                }
+                self.emit_op(OpCode::Return);
+            }
+            Stmt::Var(name, initializer) => {
+                match initializer {
+                    Some(initializer) => self.expression(initializer)?,
+                    None => self.emit_op(OpCode::Nil),
+                }
+                let global = self.declare(name)?;
+                self.define(global, name)?;
+            }
+            Stmt::While(condition, body, _keyword) => {
+                let loop_start = self.current().chunk.code.len();
+                self.expression(condition)?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(body)?;
+                self.emit_loop(loop_start)?;
+                self.patch_jump(exit_jump)?;
+                self.emit_op(OpCode::Pop);
+            }
+        }
+        Ok(())
+    }
+
+    /// Methods are compiled to closures first and `OpCode::Class` gathers
+    /// them. The class name is declared before, so methods can refer to it,
+    /// and `super` lives in a scope of its own around them.
+    fn class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Stmt],
+    ) -> Result<(), Error> {
+        let global = self.current().scope_depth == 0;
+        if !global {
+            self.emit_op(OpCode::Nil);
+            self.add_local(name)?;
+        }
+        if let Some(superclass) = superclass {
+            self.expression(superclass)?;
+            self.begin_scope();
+            let keyword = Token::with_span(SUPER, "super", None, name.span);
+            self.add_local(&keyword)?;
+        }
+
+        for method in methods {
+            if let Stmt::Function(method_name, params, body) = method {
+                let kind = if method_name.lexeme == "init" {
+                    FunctionKind::Initializer
+                } else {
+                    FunctionKind::Method
+                };
+                self.function(method_name, params, body, kind)?;
+            }
+        }
+        let count = u8::try_from(methods.len())
+            .map_err(|_| too_large(Some(name), "Too many methods in one class."))?;
+        self.emit_site(OpCode::Class, name, name.span)?;
+        self.emit_bytes(&[count, superclass.is_some() as u8]);
+
+        if global {
+            let constant = self.name_constant(name)?;
+            self.emit_op(OpCode::DefineGlobal);
+            self.emit_u16(constant);
+        } else {
+            let slot = self.resolve_local(self.functions.len() - 1, &name.lexeme);
+            self.emit_op(OpCode::SetLocal);
+            self.emit_bytes(&[slot.expect("the class was just declared")]);
+            self.emit_op(OpCode::Pop);
+        }
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        body: &[Stmt],
+        kind: FunctionKind,
+    ) -> Result<(), Error> {
+        self.functions
+            .push(FunctionState::new(&name.lexeme, params.len(), kind));
+        self.begin_scope();
+        for param in params {
+            self.add_local(param)?;
+        }
+        for statement in body {
+            self.statement(statement)?;
+        }
+        self.emit_return();
+
+        let (function, upvalues) = self.finish();
+        let constant = self.make_constant(Constant::Function(Rc::new(function)), Some(name))?;
+        self.line = name.line;
+        self.emit_op(OpCode::Closure);
+        self.emit_u16(constant);
+        for upvalue in upvalues {
+            self.emit_bytes(&[upvalue.is_local as u8, upvalue.index]);
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), Error> {
+        match expr {
+            Expr::Ternary(condition, first, second, operator) => {
+                self.expression(condition)?;
+                self.expression(first)?;
+                self.expression(second)?;
+                self.emit_site(OpCode::Ternary, operator, expr.span())?;
+            }
+            Expr::Binary(left, operator, right) if operator.token_type == COMMA => {
+                self.expression(left)?;
+                self.emit_op(OpCode::Pop);
+                self.expression(right)?;
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                let op = match operator.token_type {
+                    EQUAL_EQUAL => OpCode::Equal,
+                    BANG_EQUAL => OpCode::NotEqual,
+                    GREATER => OpCode::Greater,
+                    GREATER_EQUAL => OpCode::GreaterEqual,
+                    LESS => OpCode::Less,
+                    LESS_EQUAL => OpCode::LessEqual,
+                    PLUS => OpCode::Add,
+                    MINUS => OpCode::Subtract,
+                    STAR => OpCode::Multiply,
+                    SLASH => OpCode::Divide,
+                    TILDE_SLASH => OpCode::IntDivide,
+                    PERCENT => OpCode::Modulo,
+                    _ => {
+                        return Err(Error::CompileTimeError {
+                            token: Some(operator.clone()),
+                            code: ErrorCode::UnknownOperator,
+                            message: String::from(
+                                "Cannot fathom the binary operation. Check your code again",
+                            ),
+                        })
+                    }
+                };
+                self.emit_site(op, operator, expr.span())?;
+            }
+            Expr::Call(callee, paren, arguments) => {
+                self.expression(callee)?;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                let count = u8::try_from(arguments.len())
+                    .map_err(|_| too_large(Some(paren), "Too many arguments in one call."))?;
+                self.line = paren.line;
+                self.emit_op(OpCode::Call);
+                self.emit_bytes(&[count]);
+                let site = self.make_site(paren, expr.span())?;
+                self.emit_u16(site);
+            }
+            Expr::Get(object, name) => {
+                self.expression(object)?;
+                self.emit_site(OpCode::GetProperty, name, expr.span())?;
+            }
+            Expr::Grouping(expr, _span) => self.expression(expr)?,
+            Expr::Index(object, bracket, index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.emit_site(OpCode::GetIndex, bracket, expr.span())?;
+            }
+            Expr::IndexSet(object, bracket, index, value) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.emit_site(OpCode::SetIndex, bracket, expr.span())?;
+            }
+            Expr::List(elements, span) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                let count = u16::try_from(elements.len())
+                    .map_err(|_| too_large(None, "Too many items in one list literal."))?;
+                self.line = span.line;
+                self.emit_op(OpCode::List);
+                self.emit_u16(count);
+            }
+            Expr::Map(entries, span) => {
+                self.line = span.line;
+                self.emit_op(OpCode::Map);
+                for (key, colon, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                    self.emit_site(OpCode::MapEntry, colon, colon.span)?;
+                }
+            }
+            Expr::Logical(left, operator, right) => {
+                self.expression(left)?;
+                if operator.token_type == OR {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(else_jump)?;
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_op(OpCode::Pop);
+                    self.expression(right)?;
+                    self.patch_jump(end_jump)?;
+                }
+            }
+            Expr::LiteralExpr(literal, span) => {
+                self.line = span.line;
+                let constant = match literal {
+                    Some(Literal::Int(num)) => Constant::Int(*num),
+                    Some(Literal::Number(num)) => Constant::Number(*num),
+                    Some(Literal::BigInt(num)) => Constant::BigInt(Rc::clone(num)),
+                    Some(Literal::Decimal(num)) => Constant::Decimal(Rc::clone(num)),
+                    Some(Literal::Str(string)) => Constant::Str(string.clone()),
+                    Some(Literal::Bool(true)) => {
+                        self.emit_op(OpCode::True);
+                        return Ok(());
+                    }
+                    Some(Literal::Bool(false)) => {
+                        self.emit_op(OpCode::False);
+                        return Ok(());
+                    }
+                    Some(Literal::Nil) | None => {
+                        self.emit_op(OpCode::Nil);
+                        return Ok(());
+                    }
+                };
+                let constant = self.make_constant(constant, None)?;
+                self.emit_op(OpCode::Constant);
+                self.emit_u16(constant);
+            }
+            Expr::Set(object, name, value) => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.emit_site(OpCode::SetProperty, name, expr.span())?;
+            }
+            Expr::Super(keyword, method, _depth) => {
+                let this = Token::with_span(THIS, "this", None, keyword.span);
+                self.variable(&this)?;
+                self.variable(keyword)?;
+                self.emit_site(OpCode::GetSuper, method, expr.span())?;
+            }
+            Expr::This(keyword, _depth) => self.variable(keyword)?,
+            Expr::Unary(operator, right) => {
+                self.expression(right)?;
+                match operator.token_type {
+                    BANG => {
+                        self.line = operator.line;
+                        self.emit_op(OpCode::Not);
+                    }
+                    MINUS => self.emit_site(OpCode::Negate, operator, expr.span())?,
+                    _ => {
+                        self.emit_op(OpCode::Pop);
+                        self.emit_op(OpCode::Nil);
+                    }
+                }
+            }
+            Expr::Variable(name, _depth) => self.variable(name)?,
+            Expr::Assign { name, value, .. } => {
+                self.expression(value)?;
+                self.line = name.line;
+                let function = self.functions.len() - 1;
+                if let Some(slot) = self.resolve_local(function, &name.lexeme) {
+                    self.emit_op(OpCode::SetLocal);
+                    self.emit_bytes(&[slot]);
+                } else if let Some(index) = self.resolve_upvalue(function, name)? {
+                    self.emit_op(OpCode::SetUpvalue);
+                    self.emit_bytes(&[index]);
+                } else {
+                    self.emit_site(OpCode::SetGlobal, name, name.span)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn variable(&mut self, name: &Token) -> Result<(), Error> {
+        self.line = name.line;
+        let function = self.functions.len() - 1;
+        if let Some(slot) = self.resolve_local(function, &name.lexeme) {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_bytes(&[slot]);
+        } else if let Some(index) = self.resolve_upvalue(function, name)? {
+            self.emit_op(OpCode::GetUpvalue);
+            self.emit_bytes(&[index]);
+        } else {
+            self.emit_site(OpCode::GetGlobal, name, name.span)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_local(&self, function: usize, name: &Symbol) -> Option<u8> {
+        let locals = &self.functions[function].locals;
+        // slots are checked to fit in a byte when they're added
+        locals
+            .iter()
+            .rposition(|local| local.name == *name)
+            .map(|slot| slot as u8)
+    }
+
+    /// Finds `name` in the functions around `function`, threading an upvalue
+    /// through each one in between.
+    fn resolve_upvalue(&mut self, function: usize, name: &Token) -> Result<Option<u8>, Error> {
+        if function == 0 {
+            return Ok(None);
+        }
+        if let Some(slot) = self.resolve_local(function - 1, &name.lexeme) {
+            self.functions[function - 1].locals[slot as usize].captured = true;
+            return self.add_upvalue(function, slot, true, name).map(Some);
+        }
+        match self.resolve_upvalue(function - 1, name)? {
+            Some(index) => self.add_upvalue(function, index, false, name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(
+        &mut self,
+        function: usize,
+        index: u8,
+        is_local: bool,
+        name: &Token,
+    ) -> Result<u8, Error> {
+        let upvalue = Upvalue { index, is_local };
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(existing as u8);
+        }
+        if upvalues.len() > u8::MAX as usize {
+            return Err(Error::CompileTimeError {
+                token: Some(name.clone()),
+                code: ErrorCode::TooManyLocals,
+                message: String::from("Too many closure variables in function."),
+            });
+        }
+        upvalues.push(upvalue);
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    /// Declares `name` in the current scope, as a local unless the scope is
+    /// the top level. Returns whether it is a global.
+    fn declare(&mut self, name: &Token) -> Result<bool, Error> {
+        if self.current().scope_depth == 0 {
+            return Ok(true);
+        }
+        self.add_local(name)?;
+        Ok(false)
+    }
+
+    /// Binds the value on top of the stack to a declared variable. A local
+    /// already owns that slot, so only a global needs an instruction.
+    fn define(&mut self, global: bool, name: &Token) -> Result<(), Error> {
+        if global {
+            let constant = self.name_constant(name)?;
+            self.line = name.line;
+            self.emit_op(OpCode::DefineGlobal);
+            self.emit_u16(constant);
+        }
+        Ok(())
+    }
+
+    fn add_local(&mut self, name: &Token) -> Result<(), Error> {
+        let current = self.current();
+        if current.locals.len() > u8::MAX as usize {
+            return Err(Error::CompileTimeError {
+                token: Some(name.clone()),
+                code: ErrorCode::TooManyLocals,
+                message: String::from("Too many local variables in function."),
+            });
+        }
+        let depth = current.scope_depth;
+        current.locals.push(Local {
+            name: name.lexeme.clone(),
+            depth,
+            captured: false,
+        });
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    /// Drops the scope's locals, moving the ones closures captured off the
+    /// stack first.
+    fn end_scope(&mut self) {
+        let current = self.current();
+        current.scope_depth -= 1;
+        let depth = current.scope_depth;
+        let mut ops = Vec::new();
+        while let Some(local) = current.locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            ops.push(if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+            current.locals.pop();
+        }
+        for op in ops {
+            self.emit_op(op);
+        }
+    }
+
+    fn name_constant(&mut self, name: &Token) -> Result<u16, Error> {
+        self.make_constant(Constant::Str(name.lexeme.clone()), Some(name))
+    }
+
+    fn make_constant(&mut self, constant: Constant, token: Option<&Token>) -> Result<u16, Error> {
+        let index = self.current().chunk.add_constant(constant);
+        u16::try_from(index).map_err(|_| too_large(token, "Too many constants in one chunk."))
+    }
+
+    fn make_site(&mut self, token: &Token, span: Span) -> Result<u16, Error> {
+        let index = self.current().chunk.add_site(token, span);
+        u16::try_from(index).map_err(|_| too_large(Some(token), "Too much code in one chunk."))
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_bytes(&[op as u8]);
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) {
+        let line = self.line;
+        let chunk = &mut self.current().chunk;
+        for byte in bytes {
+            chunk.write(*byte, line);
+        }
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        self.emit_bytes(&value.to_be_bytes());
+    }
+
+    /// Emits `op` pointing back at `token` in the source.
+    fn emit_site(&mut self, op: OpCode, token: &Token, span: Span) -> Result<(), Error> {
+        self.line = token.line;
+        let site = self.make_site(token, span)?;
+        self.emit_op(op);
+        self.emit_u16(site);
+        Ok(())
+    }
+
+    /// What a function hands back without an explicit value: `nil`, or the
+    /// instance for an initializer.
+    fn emit_default_result(&mut self) {
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit_op(OpCode::GetLocal);
+            self.emit_bytes(&[0]);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+    }
+
+    fn emit_return(&mut self) {
+        self.emit_default_result();
+        self.emit_op(OpCode::Return);
+    }
+
+    /// Emits a jump with a placeholder offset and returns where to patch it.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_u16(u16::MAX);
+        self.current().chunk.code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) -> Result<(), Error> {
+        let code = &mut self.current().chunk.code;
+        let jump = u16::try_from(code.len() - offset - 2)
+            .map_err(|_| too_large(None, "Too much code to jump over."))?;
+        code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) -> Result<(), Error> {
+        self.emit_op(OpCode::Loop);
+        let jump = u16::try_from(self.current().chunk.code.len() - loop_start + 2)
+            .map_err(|_| too_large(None, "Loop body is too large."))?;
+        self.emit_u16(jump);
+        Ok(())
+    }
+}
@@ -0,0 +1,425 @@
+use crate::error::{Error, ErrorCode, Frame};
+use crate::token::Span;
+use std::env;
+use std::io::{self, IsTerminal};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// Whether rendered diagnostics may contain ANSI escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Plain,
+    Ansi,
+}
+
+impl ColorMode {
+    /// Colors only when stderr is a terminal and `NO_COLOR` isn't set, so CI
+    /// logs and redirected output stay plain.
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal() {
+            ColorMode::Ansi
+        } else {
+            ColorMode::Plain
+        }
+    }
+}
+
+/// How the runner reports diagnostics: rendered for people, or one JSON
+/// object per line for tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+fn paint(text: &str, style: &str, color: ColorMode) -> String {
+    match color {
+        ColorMode::Plain => String::from(text),
+        ColorMode::Ansi => format!("{}{}{}", style, text, RESET),
+    }
+}
+
+/// A message about a location in the source, ready to be shown to the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+    /// Calls active when a runtime error was raised, innermost first.
+    pub trace: Vec<Frame>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: &str) -> Self {
+        Diagnostic {
+            severity,
+            code: None,
+            message: String::from(message),
+            span: None,
+            notes: Vec::new(),
+            help: None,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn error(message: &str) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(String::from(note));
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(String::from(help));
+        self
+    }
+
+    pub fn with_trace(mut self, trace: Vec<Frame>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Where each call in the trace had got to, innermost first: the
+    /// innermost function stopped at the error itself, every other one at
+    /// the call below it, and the script at the outermost call.
+    fn stack(&self) -> Vec<(&str, Option<Span>)> {
+        if self.trace.is_empty() {
+            return Vec::new();
+        }
+        let names = self
+            .trace
+            .iter()
+            .map(|frame| frame.function.as_str())
+            .chain(Some("<script>"));
+        let positions = Some(self.span)
+            .into_iter()
+            .chain(self.trace.iter().map(|frame| Some(frame.call_site)));
+        names
+            .zip(positions)
+            .map(|(name, span)| (name, span.filter(|span| span.line > 0)))
+            .collect()
+    }
+
+    /// Renders the header, the offending source line with the span
+    /// underlined, and any notes and help. `file` names the source in the
+    /// `-->` line; spans on line 0 come from errors without a location and
+    /// only get the header.
+    pub fn render(&self, source: &str, file: Option<&str>, color: ColorMode) -> String {
+        let label_style = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+        let label = match self.code {
+            Some(code) => format!("{}[{}]", self.severity.as_str(), code),
+            None => String::from(self.severity.as_str()),
+        };
+        let mut out = format!(
+            "{}{}\n",
+            paint(&label, label_style, color),
+            paint(&format!(": {}", self.message), BOLD, color)
+        );
+
+        let snippet = self
+            .span
+            .filter(|span| span.line > 0)
+            .map(|span| (span, source.lines().nth(span.line - 1)));
+        let gutter_width = match snippet {
+            Some((span, _)) => span.line.to_string().len(),
+            None => 1,
+        };
+        let gutter = " ".repeat(gutter_width);
+        let bar = paint("|", BLUE, color);
+
+        if let Some((span, _)) = snippet {
+            out.push_str(&format!(
+                "{}{} {}:{}:{}\n",
+                gutter,
+                paint("-->", BLUE, color),
+                file.unwrap_or("<input>"),
+                span.line,
+                span.column
+            ));
+        }
+        // a compiled script runs without its source, so there's nothing to quote
+        if let Some((span, Some(line))) = snippet {
+            out.push_str(&format!("{} {}\n", gutter, bar));
+            out.push_str(&format!(
+                "{} {} {}\n",
+                paint(&span.line.to_string(), BLUE, color),
+                bar,
+                line
+            ));
+
+            // keep tabs so the caret lines up with what the terminal shows
+            let before = span.column.saturating_sub(1);
+            let padding: String = line
+                .chars()
+                .take(before)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let remaining = line.chars().count().saturating_sub(before);
+            let width = (span.end - span.start).min(remaining).max(1);
+            let marker = "^".repeat(width);
+            out.push_str(&format!(
+                "{} {} {}{}\n",
+                gutter,
+                bar,
+                padding,
+                paint(&marker, label_style, color)
+            ));
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                gutter,
+                paint("= note:", BOLD, color),
+                note
+            ));
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                gutter,
+                paint("= help:", BOLD, color),
+                help
+            ));
+        }
+        let stack = self.stack();
+        if !stack.is_empty() {
+            out.push_str(&format!("{} {}\n", gutter, paint("= trace:", BOLD, color)));
+        }
+        for (function, span) in stack {
+            let file = file.unwrap_or("<input>");
+            let location = match span {
+                Some(span) => format!("{}:{}:{}", file, span.line, span.column),
+                None => String::from(file),
+            };
+            out.push_str(&format!("{}     at {} ({})\n", gutter, function, location));
+        }
+        out
+    }
+}
+
+impl Diagnostic {
+    /// Single-line JSON object with the file, span, severity, code and
+    /// message, plus any notes, help and trace. Missing values are `null`.
+    pub fn to_json(&self, file: Option<&str>) -> String {
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        let trace: Vec<String> = self
+            .stack()
+            .into_iter()
+            .map(|(function, span)| {
+                format!(
+                    "{{\"function\":{},\"span\":{}}}",
+                    json_string(function),
+                    json_span(span)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"file\":{},\"span\":{},\"severity\":{},\"code\":{},\"message\":{},\"notes\":[{}],\"help\":{},\"trace\":[{}]}}",
+            json_or_null(file),
+            json_span(self.span.filter(|span| span.line > 0)),
+            json_string(self.severity.as_str()),
+            json_or_null(self.code.map(|code| code.as_str())),
+            json_string(&self.message),
+            notes.join(","),
+            json_or_null(self.help.as_deref()),
+            trace.join(",")
+        )
+    }
+}
+
+fn json_span(span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!(
+            "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{}}}",
+            span.start, span.end, span.line, span.column
+        ),
+        None => String::from("null"),
+    }
+}
+
+fn json_or_null(value: Option<&str>) -> String {
+    value.map_or_else(|| String::from("null"), json_string)
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(error: &Error) -> Self {
+        let mut diagnostic = Diagnostic::error(&error.message());
+        diagnostic.span = error.span();
+        diagnostic.trace = error.trace().to_vec();
+        if let Some(code) = error.code() {
+            diagnostic.code = Some(code);
+            diagnostic.help = code.help().map(String::from);
+        }
+        diagnostic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn underlines_the_span() {
+        let source = "var a = 1;\nprint a + foo;";
+        let diagnostic = Diagnostic::error("Undefined variable 'foo'.")
+            .with_code(ErrorCode::UndefinedVariable)
+            .with_span(Span::new(21, 24, 2, 11))
+            .with_note("globals must be defined before they are read");
+        assert_eq!(
+            diagnostic.render(source, Some("main.lox"), ColorMode::Plain),
+            "error[E1000]: Undefined variable 'foo'.\n \
+             --> main.lox:2:11\n  \
+             |\n\
+             2 | print a + foo;\n  \
+             |           ^^^\n  \
+             = note: globals must be defined before they are read\n"
+        );
+    }
+
+    #[test]
+    fn converts_errors_with_code_and_help() {
+        let (_tokens, errors) = Scanner::new(String::from("print \"oops;")).scan_tokens();
+        let diagnostic = Diagnostic::from(&errors[0]);
+        assert_eq!(diagnostic.code, Some(ErrorCode::UnterminatedString));
+        assert_eq!(diagnostic.span.map(|span| span.column), Some(7));
+
+        let plain = diagnostic.render("print \"oops;", None, ColorMode::Plain);
+        assert!(plain.starts_with("error[E0003]: Unterminated string\n"));
+        assert!(plain.contains("--> <input>:1:7"));
+        assert!(plain.contains("= help: add a closing"));
+        assert!(!plain.contains('\x1b'));
+
+        let colored = diagnostic.render("print \"oops;", None, ColorMode::Ansi);
+        assert!(colored.contains("\x1b[1;31merror[E0003]\x1b[0m"));
+    }
+
+    #[test]
+    fn json_is_one_line_per_diagnostic() {
+        let diagnostic = Diagnostic::error("Undefined variable 'a\"b'.")
+            .with_code(ErrorCode::UndefinedVariable)
+            .with_span(Span::new(6, 7, 1, 7));
+        assert_eq!(
+            diagnostic.to_json(Some("dir\\main.lox")),
+            "{\"file\":\"dir\\\\main.lox\",\
+             \"span\":{\"start\":6,\"end\":7,\"line\":1,\"column\":7},\
+             \"severity\":\"error\",\"code\":\"E1000\",\
+             \"message\":\"Undefined variable 'a\\\"b'.\",\"notes\":[],\"help\":null,\"trace\":[]}"
+        );
+        assert_eq!(
+            Diagnostic::error("x\ny").with_note("n").to_json(None),
+            "{\"file\":null,\"span\":null,\"severity\":\"error\",\"code\":null,\
+             \"message\":\"x\\ny\",\"notes\":[\"n\"],\"help\":null,\"trace\":[]}"
+        );
+    }
+
+    #[test]
+    fn prints_the_trace_innermost_first() {
+        let source = "fun f() {\n  return nil + 1;\n}\nfun g() { f(); }\ng();";
+        let frame = |function: &str, span| Frame {
+            function: String::from(function),
+            call_site: span,
+        };
+        let diagnostic = Diagnostic::error("Mismatched types.")
+            .with_span(Span::new(23, 24, 2, 14))
+            .with_trace(vec![
+                frame("f", Span::new(41, 44, 4, 11)),
+                frame("g", Span::new(49, 52, 5, 1)),
+            ]);
+        assert!(diagnostic
+            .render(source, Some("main.lox"), ColorMode::Plain)
+            .ends_with(
+                "  = trace:\n      at f (main.lox:2:14)\n      at g (main.lox:4:11)\n      \
+                 at <script> (main.lox:5:1)\n"
+            ));
+        assert!(diagnostic.to_json(None).ends_with(
+            "\"trace\":[{\"function\":\"f\",\"span\":{\"start\":23,\"end\":24,\"line\":2,\"column\":14}},\
+             {\"function\":\"g\",\"span\":{\"start\":41,\"end\":44,\"line\":4,\"column\":11}},\
+             {\"function\":\"<script>\",\"span\":{\"start\":49,\"end\":52,\"line\":5,\"column\":1}}]}"
+        ));
+    }
+
+    #[test]
+    fn errors_without_a_location_only_get_a_header() {
+        let diagnostic = Diagnostic::error("something broke").with_help("try again");
+        assert_eq!(
+            diagnostic.render("", None, ColorMode::Plain),
+            "error: something broke\n  = help: try again\n"
+        );
+    }
+
+    #[test]
+    fn errors_without_source_only_get_a_location() {
+        let diagnostic = Diagnostic::error("something broke").with_span(Span::new(4, 5, 3, 2));
+        assert_eq!(
+            diagnostic.render("", Some("main.loxc"), ColorMode::Plain),
+            "error: something broke\n --> main.loxc:3:2\n"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+use std::fmt::Write;
+
+fn op_name(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Constant => "CONSTANT",
+        OpCode::Nil => "NIL",
+        OpCode::True => "TRUE",
+        OpCode::False => "FALSE",
+        OpCode::Pop => "POP",
+        OpCode::GetLocal => "GET_LOCAL",
+        OpCode::SetLocal => "SET_LOCAL",
+        OpCode::DefineGlobal => "DEFINE_GLOBAL",
+        OpCode::GetGlobal => "GET_GLOBAL",
+        OpCode::SetGlobal => "SET_GLOBAL",
+        OpCode::GetUpvalue => "GET_UPVALUE",
+        OpCode::SetUpvalue => "SET_UPVALUE",
+        OpCode::GetProperty => "GET_PROPERTY",
+        OpCode::SetProperty => "SET_PROPERTY",
+        OpCode::GetSuper => "GET_SUPER",
+        OpCode::GetIndex => "GET_INDEX",
+        OpCode::SetIndex => "SET_INDEX",
+        OpCode::Equal => "EQUAL",
+        OpCode::NotEqual => "NOT_EQUAL",
+        OpCode::Greater => "GREATER",
+        OpCode::GreaterEqual => "GREATER_EQUAL",
+        OpCode::Less => "LESS",
+        OpCode::LessEqual => "LESS_EQUAL",
+        OpCode::Add => "ADD",
+        OpCode::Subtract => "SUBTRACT",
+        OpCode::Multiply => "MULTIPLY",
+        OpCode::Divide => "DIVIDE",
+        OpCode::IntDivide => "INT_DIVIDE",
+        OpCode::Modulo => "MODULO",
+        OpCode::Negate => "NEGATE",
+        OpCode::Not => "NOT",
+        OpCode::Ternary => "TERNARY",
+        OpCode::Print => "PRINT",
+        OpCode::Jump => "JUMP",
+        OpCode::JumpIfFalse => "JUMP_IF_FALSE",
+        OpCode::Loop => "LOOP",
+        OpCode::Call => "CALL",
+        OpCode::Closure => "CLOSURE",
+        OpCode::CloseUpvalue => "CLOSE_UPVALUE",
+        OpCode::Return => "RETURN",
+        OpCode::Class => "CLASS",
+        OpCode::List => "LIST",
+        OpCode::Map => "MAP",
+        OpCode::MapEntry => "MAP_ENTRY",
+    }
+}
+
+fn describe(constant: &Constant) -> String {
+    match constant {
+        Constant::Function(function) => format!("<fn {}>", function.name),
+        other => other
+            .value()
+            .map_or_else(String::new, |value| format!("'{}'", value)),
+    }
+}
+
+impl Function {
+    /// Lists the function's bytecode, followed by that of every function
+    /// declared inside it.
+    pub fn disassemble(&self) -> String {
+        let mut out = format!("== {} ==\n", self.name);
+        let mut offset = 0;
+        while offset < self.chunk.code.len() {
+            offset = self.chunk.disassemble_instruction(offset, &mut out);
+        }
+        for constant in &self.chunk.constants {
+            if let Constant::Function(function) = constant {
+                out.push('\n');
+                out.push_str(&function.disassemble());
+            }
+        }
+        out
+    }
+}
+
+impl Chunk {
+    /// Appends one line per instruction to `out`: offset, source line (`|`
+    /// when unchanged), opcode and operands with whatever they refer to
+    /// resolved. Returns the offset of the next instruction.
+    pub fn disassemble_instruction(&self, offset: usize, out: &mut String) -> usize {
+        let line = self.line(offset);
+        if offset > 0 && line == self.line(offset - 1) {
+            write!(out, "{:04}    | ", offset).unwrap();
+        } else {
+            write!(out, "{:04} {:>4} ", offset, line).unwrap();
+        }
+
+        let op = match OpCode::from_byte(self.code[offset]) {
+            Some(op) => op,
+            None => {
+                writeln!(out, "unknown opcode {}", self.code[offset]).unwrap();
+                return offset + 1;
+            }
+        };
+        let name = op_name(op);
+        let site = |index: u16| format!("'{}'", self.sites[index as usize].token.lexeme);
+
+        match op {
+            OpCode::Constant | OpCode::DefineGlobal => {
+                let index = self.read_u16(offset + 1);
+                let constant = describe(&self.constants[index as usize]);
+                writeln!(out, "{:<16} {:>4} {}", name, index, constant).unwrap();
+                offset + 3
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                writeln!(out, "{:<16} {:>4}", name, self.code[offset + 1]).unwrap();
+                offset + 2
+            }
+            OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::GetIndex
+            | OpCode::SetIndex
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::IntDivide
+            | OpCode::Modulo
+            | OpCode::Negate
+            | OpCode::Ternary
+            | OpCode::Print
+            | OpCode::MapEntry => {
+                let index = self.read_u16(offset + 1);
+                writeln!(out, "{:<16} {:>4} {}", name, index, site(index)).unwrap();
+                offset + 3
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let jump = self.read_u16(offset + 1) as usize;
+                let target = if op == OpCode::Loop {
+                    offset + 3 - jump
+                } else {
+                    offset + 3 + jump
+                };
+                writeln!(out, "{:<16} {:>4} -> {:04}", name, jump, target).unwrap();
+                offset + 3
+            }
+            OpCode::Call => {
+                let count = self.code[offset + 1];
+                let index = self.read_u16(offset + 2);
+                writeln!(out, "{:<16} {:>4} args {}", name, count, site(index)).unwrap();
+                offset + 4
+            }
+            OpCode::Closure => {
+                let index = self.read_u16(offset + 1);
+                let constant = &self.constants[index as usize];
+                writeln!(out, "{:<16} {:>4} {}", name, index, describe(constant)).unwrap();
+                let upvalues = match constant {
+                    Constant::Function(function) => function.upvalues,
+                    _ => 0,
+                };
+                let mut offset = offset + 3;
+                for _ in 0..upvalues {
+                    let kind = if self.code[offset] == 1 {
+                        "local"
+                    } else {
+                        "upvalue"
+                    };
+                    writeln!(
+                        out,
+                        "{:04}    |   {} {}",
+                        offset,
+                        kind,
+                        self.code[offset + 1]
+                    )
+                    .unwrap();
+                    offset += 2;
+                }
+                offset
+            }
+            OpCode::Class => {
+                let index = self.read_u16(offset + 1);
+                let methods = self.code[offset + 3];
+                let inherits = if self.code[offset + 4] == 1 {
+                    ", inherits"
+                } else {
+                    ""
+                };
+                writeln!(
+                    out,
+                    "{:<16} {:>4} {} {} methods{}",
+                    name,
+                    index,
+                    site(index),
+                    methods,
+                    inherits
+                )
+                .unwrap();
+                offset + 5
+            }
+            OpCode::List => {
+                let count = self.read_u16(offset + 1);
+                writeln!(out, "{:<16} {:>4} items", name, count).unwrap();
+                offset + 3
+            }
+            OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::Pop
+            | OpCode::Not
+            | OpCode::CloseUpvalue
+            | OpCode::Return
+            | OpCode::Map => {
+                writeln!(out, "{}", name).unwrap();
+                offset + 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Compiler;
+    use crate::test_support::parse;
+
+    fn disassemble(source: &str) -> String {
+        Compiler::compile(&parse(source)).unwrap().disassemble()
+    }
+
+    #[test]
+    fn lists_instructions_with_operands_and_lines() {
+        let listing = disassemble(
+            "var total = 1.5;\nfun add(n) {\n  total = total + n;\n}\nwhile (total < 3) add(1);",
+        );
+        let expected = "\
+== <script> ==
+0000    1 CONSTANT            0 '1.5'
+0003    | DEFINE_GLOBAL       1 'total'
+0006    2 CLOSURE             2 <fn add>
+0009    | DEFINE_GLOBAL       3 'add'
+0012    5 GET_GLOBAL          0 'total'
+0015    | CONSTANT            4 '3'
+0018    | LESS                1 '<'
+0021    | JUMP_IF_FALSE      15 -> 0039
+0024    | POP
+0025    | GET_GLOBAL          2 'add'
+0028    | CONSTANT            5 '1'
+0031    | CALL                1 args ')'
+0035    | POP
+0036    | LOOP               27 -> 0012
+0039    | POP
+0040    | NIL
+0041    | RETURN
+";
+        assert!(
+            listing.starts_with(expected),
+            "unexpected listing:\n{}",
+            listing
+        );
+        assert!(listing.contains(
+            "\
+== add ==
+0000    3 GET_GLOBAL          0 'total'
+0003    | GET_LOCAL           1
+0005    | ADD                 1 '+'
+0008    | SET_GLOBAL          2 'total'
+0011    | POP
+0012    | NIL
+0013    | RETURN
+"
+        ));
+    }
+
+    #[test]
+    fn closures_list_their_upvalues() {
+        let listing = disassemble("fun outer() { var x = 1; fun inner() { return x; } }");
+        assert!(listing.contains("CLOSURE             1 <fn inner>\n0006    |   local 1\n"));
+        assert!(listing.contains("== inner ==\n0000    1 GET_UPVALUE         0\n"));
+    }
+}
@@ -1,12 +1,15 @@
-use crate::error::Error;
+use crate::error::{Error, ErrorCode};
+use crate::gc::{address, trace_value, Address, Trace};
+use crate::interner::{intern, Symbol};
 use crate::token::Token;
 use crate::typer::Typer;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-#[derive(Clone)]
 pub struct Environment {
-    values: HashMap<String, Typer>,
-    enclosing: Option<Box<Environment>>,
+    values: HashMap<Symbol, Typer>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
@@ -17,43 +20,50 @@ impl Environment {
         }
     }
 
-    pub fn new(enclosing: Self) -> Self {
+    pub fn new(enclosing: Rc<RefCell<Environment>>) -> Self {
         Self {
             values: HashMap::new(),
-            enclosing: Some(Box::new(enclosing)),
+            enclosing: Some(enclosing),
         }
     }
 
-    pub fn get(&self, name: &Token) -> Result<&Typer, Error> {
+    pub fn get(&self, name: &Token) -> Result<Typer, Error> {
         match self.values.get(&name.lexeme) {
-            Some(value) => return Ok(value),
+            Some(value) => Ok(value.clone()),
             None => match &self.enclosing {
-                Some(enclosing) => return enclosing.get(name),
+                Some(enclosing) => enclosing.borrow().get(name),
                 None => Err(Error::RuntimeError {
                     token: Some(name.clone()),
+                    code: ErrorCode::UndefinedVariable,
                     message: format!("Undefined variable '{}'.", name.lexeme),
+                    trace: Vec::new(),
                 }),
             },
         }
     }
 
-    pub fn define(&mut self, name: String, value: Option<Typer>) {
-        if let Some(value) = value {
-            self.values.insert(name, value);
-        }
+    /// Looks `name` up in this scope only, without an error when it's missing.
+    pub fn get_local(&self, name: &str) -> Option<Typer> {
+        self.values.get(&intern(name)).cloned()
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Option<Typer>) {
+        self.values.insert(name, value.unwrap_or(Typer::Nil));
     }
 
     pub fn assign(&mut self, name: &Token, value: Typer) -> Result<(), Error> {
-        match self.values.get(&name.lexeme) {
+        match self.values.get_mut(&name.lexeme) {
             Some(prev_value) => {
-                self.values.insert(name.lexeme, value);
+                *prev_value = value;
             }
             None => {
-                return match &mut self.enclosing {
-                    Some(enclosing) => enclosing.assign(name, value),
+                return match &self.enclosing {
+                    Some(enclosing) => enclosing.borrow_mut().assign(name, value),
                     None => Err(Error::RuntimeError {
                         token: Some(name.clone()),
+                        code: ErrorCode::UndefinedVariable,
                         message: format!("Undefined variable: {}", name.lexeme),
+                        trace: Vec::new(),
                     }),
                 }
             }
@@ -61,4 +71,70 @@ impl Environment {
 
         Ok(())
     }
+
+    /// Walks `distance` scopes out from `environment`; a distance of 0 is
+    /// `environment` itself.
+    pub fn ancestor(environment: &Rc<RefCell<Self>>, distance: usize) -> Rc<RefCell<Self>> {
+        let mut environment = Rc::clone(environment);
+        for _ in 0..distance {
+            let enclosing = match &environment.borrow().enclosing {
+                Some(enclosing) => Rc::clone(enclosing),
+                None => break,
+            };
+            environment = enclosing;
+        }
+        environment
+    }
+
+    pub fn get_at(
+        environment: &Rc<RefCell<Self>>,
+        distance: usize,
+        name: &Token,
+    ) -> Result<Typer, Error> {
+        match Self::ancestor(environment, distance)
+            .borrow()
+            .values
+            .get(&name.lexeme)
+        {
+            Some(value) => Ok(value.clone()),
+            None => Err(Error::RuntimeError {
+                token: Some(name.clone()),
+                code: ErrorCode::UndefinedVariable,
+                message: format!("Undefined variable '{}'.", name.lexeme),
+                trace: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn assign_at(environment: &Rc<RefCell<Self>>, distance: usize, name: &Token, value: Typer) {
+        Self::ancestor(environment, distance)
+            .borrow_mut()
+            .values
+            .insert(name.lexeme.clone(), value);
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        match self.try_borrow() {
+            Ok(environment) => {
+                environment
+                    .values
+                    .values()
+                    .for_each(|value| trace_value(value, visit));
+                if let Some(enclosing) = &environment.enclosing {
+                    visit(address(enclosing));
+                }
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut environment) = self.try_borrow_mut() {
+            environment.values.clear();
+            environment.enclosing = None;
+        }
+    }
 }
@@ -1,24 +1,243 @@
-use crate::token::Token;
+use crate::token::{Span, Token, TokenType};
+use crate::typer::Typer;
 use snafu::Snafu;
+use std::fmt;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
-    #[snafu(display("Syntax Error in line: {}. Error: {}", match token { Some(token) => token.to_string(), None => "None".to_string() }, message))]
+    #[snafu(display("[line {}] Error{}: {}", line_of(token), location(token), message))]
     CompileTimeError {
         token: Option<Token>,
+        code: ErrorCode,
         message: String,
     },
 
-    #[snafu(display(
-        "Error evaluating in line: {}, place: {}. Error: {}",
-        match token { Some(token) => token.line , None => 0 },
-        match token { Some(token) => token.lexeme , None => String::from("") },
-        message
-    ))]
+    /// `trace` lists the calls that were active when the error was raised,
+    /// innermost first; it is empty for errors outside any function.
+    #[snafu(display("[line {}] Error{}: {}", line_of(token), location(token), message))]
     RuntimeError {
         token: Option<Token>,
+        code: ErrorCode,
         message: String,
+        trace: Vec<Frame>,
     },
+
+    /// Not a real failure: unwinds a `return` statement up to the enclosing call.
+    #[snafu(display("Return outside of a function with value: {}", value))]
+    Return { value: Typer },
+}
+
+/// One active call: the function being run and where it was called from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Span,
+}
+
+fn line_of(token: &Option<Token>) -> usize {
+    token.as_ref().map_or(0, |token| token.line)
+}
+
+fn location(token: &Option<Token>) -> String {
+    match token {
+        Some(token) if token.token_type == TokenType::EOF => String::from(" at end"),
+        Some(token) => format!(" at '{}'", token.lexeme),
+        None => String::new(),
+    }
+}
+
+impl Error {
+    /// Source range the error points at, when it is tied to a token.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::CompileTimeError { token, .. } | Error::RuntimeError { token, .. } => {
+                token.as_ref().map(|token| token.span)
+            }
+            Error::Return { .. } => None,
+        }
+    }
+
+    /// Points an error raised without a location, such as one from a
+    /// native function, at `token`.
+    pub fn or_at(self, token: &Token) -> Self {
+        match self {
+            Error::RuntimeError {
+                token: None,
+                code,
+                message,
+                trace,
+            } => Error::RuntimeError {
+                token: Some(token.clone()),
+                code,
+                message,
+                trace,
+            },
+            error => error,
+        }
+    }
+
+    /// Records the call stack on a runtime error that doesn't have one yet.
+    /// The innermost call an error unwinds through sets it, so outer calls
+    /// leave it alone.
+    pub fn with_trace(self, frames: &[Frame]) -> Self {
+        match self {
+            Error::RuntimeError {
+                token,
+                code,
+                message,
+                trace,
+            } if trace.is_empty() => Error::RuntimeError {
+                token,
+                code,
+                message,
+                trace: frames.iter().rev().cloned().collect(),
+            },
+            error => error,
+        }
+    }
+
+    pub fn trace(&self) -> &[Frame] {
+        match self {
+            Error::RuntimeError { trace, .. } => trace,
+            _ => &[],
+        }
+    }
+
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Error::CompileTimeError { code, .. } | Error::RuntimeError { code, .. } => Some(*code),
+            Error::Return { .. } => None,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Error::CompileTimeError { message, .. } | Error::RuntimeError { message, .. } => {
+                message.clone()
+            }
+            Error::Return { .. } => self.to_string(),
+        }
+    }
+}
+
+/// Stable identifier for every kind of error. Scanner errors are numbered
+/// from E0001, parser errors from E0100, resolver errors from E0200, bytecode
+/// compiler errors from E0300, bytecode file errors from E0400 and runtime
+/// errors from E1000; a code is never reused once published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnexpectedCharacter,
+    InvalidNumber,
+    UnterminatedString,
+
+    ExpectedToken,
+    ExpectedExpression,
+    InvalidAssignmentTarget,
+    TooManyArguments,
+
+    ReadInOwnInitializer,
+    DuplicateVariable,
+    ReturnAtTopLevel,
+    ReturnValueFromInitializer,
+    ThisOutsideClass,
+    SuperOutsideClass,
+    SuperWithoutSuperclass,
+    InheritFromSelf,
+
+    TooManyLocals,
+    ChunkTooLarge,
+
+    InvalidBytecode,
+    BytecodeVersionMismatch,
+
+    UndefinedVariable,
+    TypeMismatch,
+    DivisionByZero,
+    UnknownOperator,
+    ArityMismatch,
+    NotCallable,
+    UndefinedProperty,
+    NotAnInstance,
+    SuperclassNotClass,
+    StackOverflow,
+    OutputFailed,
+    InvalidArgument,
+    CapabilityDisabled,
+    IoFailed,
+    IndexOutOfBounds,
+    KeyNotFound,
+    IntegerOverflow,
 }
 
-impl Error {}
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        use ErrorCode::*;
+        match self {
+            UnexpectedCharacter => "E0001",
+            InvalidNumber => "E0002",
+            UnterminatedString => "E0003",
+
+            ExpectedToken => "E0100",
+            ExpectedExpression => "E0101",
+            InvalidAssignmentTarget => "E0102",
+            TooManyArguments => "E0103",
+
+            ReadInOwnInitializer => "E0200",
+            DuplicateVariable => "E0201",
+            ReturnAtTopLevel => "E0202",
+            ReturnValueFromInitializer => "E0203",
+            ThisOutsideClass => "E0204",
+            SuperOutsideClass => "E0205",
+            SuperWithoutSuperclass => "E0206",
+            InheritFromSelf => "E0207",
+
+            TooManyLocals => "E0300",
+            ChunkTooLarge => "E0301",
+
+            InvalidBytecode => "E0400",
+            BytecodeVersionMismatch => "E0401",
+
+            UndefinedVariable => "E1000",
+            TypeMismatch => "E1001",
+            DivisionByZero => "E1002",
+            UnknownOperator => "E1003",
+            ArityMismatch => "E1004",
+            NotCallable => "E1005",
+            UndefinedProperty => "E1006",
+            NotAnInstance => "E1007",
+            SuperclassNotClass => "E1008",
+            StackOverflow => "E1009",
+            OutputFailed => "E1010",
+            InvalidArgument => "E1011",
+            CapabilityDisabled => "E1012",
+            IoFailed => "E1013",
+            IndexOutOfBounds => "E1014",
+            KeyNotFound => "E1015",
+            IntegerOverflow => "E1016",
+        }
+    }
+
+    /// A short hint shown under diagnostics carrying this code, if any.
+    pub fn help(self) -> Option<&'static str> {
+        use ErrorCode::*;
+        match self {
+            UnterminatedString => Some("add a closing '\"' to end the string"),
+            InvalidAssignmentTarget => Some("only variables and fields can be assigned to"),
+            ReadInOwnInitializer => Some("rename the local or initialize it from another value"),
+            ReturnAtTopLevel => Some("'return' is only allowed inside a function or method"),
+            BytecodeVersionMismatch => Some("recompile the script with 'eksc compile'"),
+            UndefinedVariable => Some("declare it with 'var' before using it"),
+            NotCallable => Some("only functions and classes can be called"),
+            CapabilityDisabled => {
+                Some("trusted hosts can enable it with Runner::enable_io or --allow-io")
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -1,16 +1,36 @@
 use crate::error::Error;
-use crate::token::{Literal, Token};
+use crate::interpreter::Interpreter;
+use crate::token::{Literal, Span, Token};
 use crate::typer::Typer;
+use std::cell::Cell;
+
+/// Number of scopes between a variable use and its binding, filled in by the
+/// resolver. `None` means the variable is a global.
+pub type Depth = Cell<Option<usize>>;
 
 #[allow(dead_code)]
 pub enum Expr {
     Ternary(Box<Expr>, Box<Expr>, Box<Expr>, Token),
     Binary(Box<Expr>, Token, Box<Expr>),
-    Grouping(Box<Expr>),
-    LiteralExpr(Option<Literal>),
+    Call(Box<Expr>, Token, Vec<Expr>), // callee, closing paren, arguments
+    Get(Box<Expr>, Token),             // object, name
+    Grouping(Box<Expr>, Span),         // expression, span including the parentheses
+    Index(Box<Expr>, Token, Box<Expr>), // object, closing bracket, index
+    IndexSet(Box<Expr>, Token, Box<Expr>, Box<Expr>), // object, closing bracket, index, value
+    List(Vec<Expr>, Span),             // elements, span including the brackets
+    Map(Vec<(Expr, Token, Expr)>, Span), // key, colon, value entries; span including the braces
+    Logical(Box<Expr>, Token, Box<Expr>),
+    LiteralExpr(Option<Literal>, Span),
+    Set(Box<Expr>, Token, Box<Expr>), // object, name, value
+    Super(Token, Token, Depth),       // keyword, method, depth
+    This(Token, Depth),
     Unary(Token, Box<Expr>),
-    Variable(Token),
-    Assign { name: Token, value: Box<Expr> },
+    Variable(Token, Depth),
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+        depth: Depth,
+    },
 }
 
 pub trait Visitor<T> {
@@ -33,36 +53,128 @@ macro_rules! parenthesize {
 }
 
 impl Expr {
-    pub fn visit_string(self) -> String {
+    pub fn visit_string(&self) -> String {
         match self {
             Expr::Ternary(condition, first, second, _operation) => {
                 parenthesize!("ternary", condition, first, second)
             }
             Expr::Binary(left, operator, right) => parenthesize!(&operator.lexeme, left, right),
-            Expr::Grouping(expr) => parenthesize!(&String::from("group"), expr),
+            Expr::Call(callee, _paren, arguments) => {
+                let mut s = format!("(call {}", callee.visit_string());
+                for argument in arguments {
+                    s.push(' ');
+                    s.push_str(&argument.visit_string());
+                }
+                s.push(')');
+                s
+            }
+            Expr::Get(object, name) => format!("(. {} {})", object.visit_string(), name.lexeme),
+            Expr::Grouping(expr, _span) => parenthesize!(&String::from("group"), expr),
+            Expr::Index(object, _bracket, index) => parenthesize!("index", object, index),
+            Expr::IndexSet(object, _bracket, index, value) => {
+                parenthesize!("index=", object, index, value)
+            }
+            Expr::List(elements, _span) => {
+                let mut s = String::from("(list");
+                for element in elements {
+                    s.push(' ');
+                    s.push_str(&element.visit_string());
+                }
+                s.push(')');
+                s
+            }
+            Expr::Set(object, name, value) => format!(
+                "(= {} {} {})",
+                object.visit_string(),
+                name.lexeme,
+                value.visit_string()
+            ),
+            Expr::Super(_keyword, method, _depth) => format!("(super {})", method.lexeme),
+            Expr::This(_keyword, _depth) => String::from("this"),
+            Expr::Logical(left, operator, right) => parenthesize!(&operator.lexeme, left, right),
+            Expr::Map(entries, _span) => {
+                let mut s = String::from("(map");
+                for (key, _colon, value) in entries {
+                    s.push_str(&format!(
+                        " ({} {})",
+                        key.visit_string(),
+                        value.visit_string()
+                    ));
+                }
+                s.push(')');
+                s
+            }
             Expr::Unary(operator, right) => parenthesize!(&operator.lexeme, right),
-            Expr::LiteralExpr(lit) => match lit {
+            Expr::LiteralExpr(lit, _span) => match lit {
                 Some(lit) => lit.to_string(),
                 None => "None".to_string(),
             },
-            Expr::Variable(var) => parenthesize!(&String::from(var.lexeme)),
-            Expr::Assign { name, value } => parenthesize!(&name.lexeme, value),
+            Expr::Variable(var, _depth) => parenthesize!(&var.lexeme),
+            Expr::Assign { name, value, .. } => parenthesize!(&name.lexeme, value),
         }
     }
 }
 
 impl Expr {
-    pub fn visit(self) -> Result<Typer, Error> {
+    /// Source range covered by the whole expression.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Ternary(condition, _first, second, _operator) => {
+                condition.span().to(second.span())
+            }
+            Expr::Binary(left, _operator, right) | Expr::Logical(left, _operator, right) => {
+                left.span().to(right.span())
+            }
+            Expr::Call(callee, paren, _arguments) => callee.span().to(paren.span),
+            Expr::Get(object, name) => object.span().to(name.span),
+            Expr::Grouping(_, span)
+            | Expr::LiteralExpr(_, span)
+            | Expr::List(_, span)
+            | Expr::Map(_, span) => *span,
+            Expr::Index(object, bracket, _index) => object.span().to(bracket.span),
+            Expr::IndexSet(object, _bracket, _index, value) => object.span().to(value.span()),
+            Expr::Set(object, _name, value) => object.span().to(value.span()),
+            Expr::Super(keyword, method, _depth) => keyword.span.to(method.span),
+            Expr::This(keyword, _depth) => keyword.span,
+            Expr::Unary(operator, right) => operator.span.to(right.span()),
+            Expr::Variable(name, _depth) => name.span,
+            Expr::Assign { name, value, .. } => name.span.to(value.span()),
+        }
+    }
+
+    pub fn visit(&self, interpreter: &Interpreter) -> Result<Typer, Error> {
         match self {
-            Expr::Binary(left, ops, right) => self.visit_binary_expr(*left, ops, *right),
-            Expr::Grouping(expr) => self.visit_grouping(*expr),
-            Expr::LiteralExpr(lit) => self.visit_literal(lit.unwrap()),
-            Expr::Unary(operator, operand) => self.visit_unary(operator, *operand),
+            Expr::Binary(left, ops, right) => self.visit_binary_expr(left, ops, right, interpreter),
+            Expr::Call(callee, paren, arguments) => {
+                self.visit_call(callee, paren, arguments, interpreter)
+            }
+            Expr::Get(object, name) => self.visit_get(object, name, interpreter),
+            Expr::Grouping(expr, _span) => self.visit_grouping(expr, interpreter),
+            Expr::Index(object, bracket, index) => {
+                self.visit_index(object, bracket, index, interpreter)
+            }
+            Expr::IndexSet(object, bracket, index, value) => {
+                self.visit_index_set(object, bracket, index, value, interpreter)
+            }
+            Expr::List(elements, _span) => self.visit_list(elements, interpreter),
+            Expr::Map(entries, _span) => self.visit_map(entries, interpreter),
+            Expr::Set(object, name, value) => self.visit_set(object, name, value, interpreter),
+            Expr::Super(keyword, method, depth) => {
+                self.visit_super(keyword, method, depth, interpreter)
+            }
+            Expr::This(keyword, depth) => self.visit_this(keyword, depth, interpreter),
+            Expr::Logical(left, operator, right) => {
+                self.visit_logical(left, operator, right, interpreter)
+            }
+            Expr::LiteralExpr(lit, _span) => self.visit_literal(lit),
+            Expr::Unary(operator, operand) => self.visit_unary(operator, operand, interpreter),
             Expr::Ternary(condition, first, second, operator) => {
-                self.visit_ternary(*condition, *first, *second, operator)
+                self.visit_ternary(condition, first, second, operator, interpreter)
+            }
+            Expr::Variable(var, depth) => self.visit_variable(var, depth, interpreter),
+            Expr::Assign { name, value, depth } => {
+                self.visit_assign(name, value, depth, interpreter)
             }
-            Expr::Variable(var) => self.visit_variable(var),
-            Expr::Assign { name, value } => self.visit_assign(*value, name),
         }
     }
 }
@@ -74,6 +186,19 @@ mod tests {
     use super::*;
     use crate::token::TokenType::*;
 
+    #[test]
+    fn spans() {
+        let left = Token::with_span(IDENTIFIER, "a", None, Span::new(4, 5, 2, 3));
+        let right = LiteralExpr(Some(Number(1.0)), Span::new(8, 9, 2, 7));
+        let expr = Assign {
+            name: left,
+            value: Box::new(right),
+            depth: Depth::default(),
+        };
+
+        assert_eq!(expr.span(), Span::new(4, 9, 2, 3));
+    }
+
     #[test]
     fn basic() {
         let tok_minus = Token::new(MINUS, "-", None, 1);
@@ -81,12 +206,19 @@ mod tests {
         let expression = Expr::Binary(
             Box::new(Expr::Unary(
                 tok_minus,
-                Box::new(Expr::LiteralExpr(Some(Literal::Number(123 as f64)))),
+                Box::new(Expr::LiteralExpr(
+                    Some(Literal::Number(123.0)),
+                    Span::default(),
+                )),
             )),
             tok_star,
-            Box::new(Expr::Grouping(Box::new(Expr::LiteralExpr(Some(
-                Literal::Number(45.67),
-            ))))),
+            Box::new(Expr::Grouping(
+                Box::new(Expr::LiteralExpr(
+                    Some(Literal::Number(45.67)),
+                    Span::default(),
+                )),
+                Span::default(),
+            )),
         );
 
         let stringified_expr: String = expression.visit_string();
@@ -103,10 +235,13 @@ mod tests {
         let expression = Binary(
             bx!(Unary(
                 Token::new(MINUS, "-", None, 1),
-                bx!(LiteralExpr(Some(Number(123 as f64))))
+                bx!(LiteralExpr(Some(Number(123.0)), Span::default()))
             )),
             Token::new(STAR, "*", None, 1),
-            bx!(Grouping(bx!(LiteralExpr(Some(Number(45.67)))))),
+            bx!(Grouping(
+                bx!(LiteralExpr(Some(Number(45.67)), Span::default())),
+                Span::default()
+            )),
         );
         let stringified_expr: String = expression.visit_string();
         assert_eq!("(* (- 123) (group 45.67))", stringified_expr);
@@ -119,21 +254,33 @@ mod tests {
         let tok_plus = Token::new(PLUS, "+", None, 1);
 
         let expression = Binary(
-            bx!(Grouping(bx!(Binary(
-                bx!(Grouping(bx!(Binary(
-                    bx!(Unary(tok_minus, bx!(LiteralExpr(Some(Number(1.2)))))),
-                    tok_plus,
-                    bx!(LiteralExpr(Some(Number(3.0))))
-                )))),
-                tok_star,
-                bx!(Grouping(bx!(Binary(
-                    bx!(LiteralExpr(Some(Number(4.1)))),
-                    tok_minus.clone(),
-                    bx!(LiteralExpr(Some(Number(3.1))))
-                ))))
-            )))),
+            bx!(Grouping(
+                bx!(Binary(
+                    bx!(Grouping(
+                        bx!(Binary(
+                            bx!(Unary(
+                                tok_minus.clone(),
+                                bx!(LiteralExpr(Some(Number(1.2)), Span::default()))
+                            )),
+                            tok_plus,
+                            bx!(LiteralExpr(Some(Number(3.0)), Span::default()))
+                        )),
+                        Span::default()
+                    )),
+                    tok_star.clone(),
+                    bx!(Grouping(
+                        bx!(Binary(
+                            bx!(LiteralExpr(Some(Number(4.1)), Span::default())),
+                            tok_minus,
+                            bx!(LiteralExpr(Some(Number(3.1)), Span::default()))
+                        )),
+                        Span::default()
+                    ))
+                )),
+                Span::default()
+            )),
             tok_star,
-            bx!(LiteralExpr(Some(Number(4.1)))),
+            bx!(LiteralExpr(Some(Number(4.1)), Span::default())),
         );
 
         // STRING FORM: ((-1.2 + 3) * (4.1 - 3.1)) * 4.1
@@ -150,18 +297,18 @@ mod tests {
         // EXPRESSION: 1 ? 2 : 0 ? 1 ? 0 : 3: 33
         let token = Token::new(TERNARY, "?..:", None, 33);
         let expr = Ternary(
-            bx!(LiteralExpr(Some(Number(1.0)))),
-            bx!(LiteralExpr(Some(Number(2.0)))),
+            bx!(LiteralExpr(Some(Number(1.0)), Span::default())),
+            bx!(LiteralExpr(Some(Number(2.0)), Span::default())),
             bx!(Ternary(
-                bx!(LiteralExpr(Some(Number(0.0)))),
+                bx!(LiteralExpr(Some(Number(0.0)), Span::default())),
                 bx!(Ternary(
-                    bx!(LiteralExpr(Some(Number(1.0)))),
-                    bx!(LiteralExpr(Some(Number(0.0)))),
-                    bx!(LiteralExpr(Some(Number(3.0)))),
-                    token,
+                    bx!(LiteralExpr(Some(Number(1.0)), Span::default())),
+                    bx!(LiteralExpr(Some(Number(0.0)), Span::default())),
+                    bx!(LiteralExpr(Some(Number(3.0)), Span::default())),
+                    token.clone(),
                 )),
-                bx!(LiteralExpr(Some(Number(33.0)))),
-                token,
+                bx!(LiteralExpr(Some(Number(33.0)), Span::default())),
+                token.clone(),
             )),
             token,
         );
@@ -0,0 +1,308 @@
+//! Frees the objects reference counting can't: cycles, such as an instance
+//! stored in one of its own fields or a function stored in the scope it
+//! closes over.
+//!
+//! Every object that can hold other values is tracked by the `Heap` when it
+//! is created. A collection marks what is reachable from the roots and
+//! sweeps the rest by clearing whatever they hold, which breaks the cycles
+//! and lets `Rc` free them. The roots are the environment chain, the VM
+//! stack and open upvalues, and any object still referenced from outside the
+//! heap, which is how values held by native code or by an expression that
+//! is halfway evaluated stay alive.
+//!
+//! Unlike a tracing collector that owns its objects, the heap only keeps
+//! `Weak` handles, and the roots beyond the interpreter's own are inferred
+//! from `Rc::strong_count` rather than registered. An object counts as held
+//! from outside when it has more strong references than the tracked objects
+//! account for, so any stray `Rc` pins it: a `Value` a host keeps, or one
+//! captured by a native closure, keeps alive every cycle reachable from it
+//! until it is dropped. Nothing reports this; the cycle just outlives the
+//! script's last use of it. The upside is that a missing root can only leak,
+//! never free something still in use. Each collection also snapshots the
+//! tracked objects and builds an index of them by address, so its cost grows
+//! with everything tracked, not only with what is live.
+
+use crate::typer::Typer;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+/// Identifies a heap object by where it lives, whatever type it is seen as.
+pub(crate) type Address = *const ();
+
+pub(crate) fn address<T: ?Sized>(object: &Rc<T>) -> Address {
+    Rc::as_ptr(object) as Address
+}
+
+/// An object the collector can see into.
+pub(crate) trait Trace {
+    /// Passes `visit` every reference this object holds to another object,
+    /// once per reference. Returns false when the object is borrowed and
+    /// can't be read; the collector then keeps it.
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool;
+
+    /// Drops what the object holds. Only garbage is cleared, and objects
+    /// that can't be part of a cycle on their own hold on to everything.
+    fn clear(&self) {}
+}
+
+pub(crate) fn trace_value(value: &Typer, visit: &mut dyn FnMut(Address)) {
+    match value {
+        Typer::Callable(callable) => visit(address(callable)),
+        Typer::Class(class) => visit(address(class)),
+        Typer::Instance(instance) => visit(address(instance)),
+        Typer::List(items) => visit(address(items)),
+        Typer::Map(map) => visit(address(map)),
+        _ => {}
+    }
+}
+
+impl Trace for RefCell<Vec<Typer>> {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        match self.try_borrow() {
+            Ok(items) => {
+                items.iter().for_each(|item| trace_value(item, visit));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut items) = self.try_borrow_mut() {
+            items.clear();
+        }
+    }
+}
+
+// collect when this many objects are tracked, however few survive
+const MIN_THRESHOLD: usize = 1024;
+
+/// Every tracked object, and when to next look for garbage among them.
+pub(crate) struct Heap {
+    objects: RefCell<HashMap<Address, Weak<dyn Trace>>>,
+    threshold: Cell<usize>,
+    growth: Cell<usize>,
+    stress: Cell<bool>,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            objects: RefCell::new(HashMap::new()),
+            threshold: Cell::new(MIN_THRESHOLD),
+            growth: Cell::new(2),
+            stress: Cell::new(false),
+        }
+    }
+}
+
+impl Heap {
+    /// Starts tracking `object`. Returns whether it's time to collect.
+    pub(crate) fn track<T: Trace + 'static>(&self, object: &Rc<T>) -> bool {
+        let weak: Weak<T> = Rc::downgrade(object);
+        let weak: Weak<dyn Trace> = weak;
+        let mut objects = self.objects.borrow_mut();
+        objects.insert(address(object), weak);
+        self.stress.get() || objects.len() >= self.threshold.get()
+    }
+
+    /// After a collection, the next one happens once the heap has grown to
+    /// `factor` times what survived.
+    pub(crate) fn set_growth(&self, factor: usize) {
+        self.growth.set(factor.max(1));
+    }
+
+    /// Collects on every allocation, to shake out objects the collector
+    /// can't see.
+    pub(crate) fn set_stress(&self, stress: bool) {
+        self.stress.set(stress);
+    }
+
+    /// Marks from `roots` and from objects referenced outside the heap,
+    /// clears everything else and returns how many objects that was.
+    pub(crate) fn collect(&self, roots: &[Address]) -> usize {
+        let objects: Vec<(Address, Rc<dyn Trace>)> = self
+            .objects
+            .borrow()
+            .iter()
+            .filter_map(|(&at, object)| Some((at, object.upgrade()?)))
+            .collect();
+        let index: HashMap<Address, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, &(at, _))| (at, i))
+            .collect();
+
+        // an object with more references than the heap accounts for is
+        // held from outside it
+        let mut inside = vec![0; objects.len()];
+        let mut pending: Vec<usize> = roots
+            .iter()
+            .filter_map(|at| index.get(at))
+            .copied()
+            .collect();
+        for (i, (_, object)) in objects.iter().enumerate() {
+            let readable = object.trace(&mut |child| {
+                if let Some(&child) = index.get(&child) {
+                    inside[child] += 1;
+                }
+            });
+            if !readable {
+                pending.push(i);
+            }
+        }
+        for (i, (_, object)) in objects.iter().enumerate() {
+            // one of the references is `objects` itself
+            if Rc::strong_count(object) > inside[i] + 1 {
+                pending.push(i);
+            }
+        }
+
+        let mut marked = vec![false; objects.len()];
+        while let Some(i) = pending.pop() {
+            if mem::replace(&mut marked[i], true) {
+                continue;
+            }
+            objects[i].1.trace(&mut |child| {
+                if let Some(&child) = index.get(&child) {
+                    pending.push(child);
+                }
+            });
+        }
+
+        let mut freed = 0;
+        for (i, (_, object)) in objects.iter().enumerate() {
+            if !marked[i] {
+                object.clear();
+                freed += 1;
+            }
+        }
+        drop(objects);
+
+        let mut objects = self.objects.borrow_mut();
+        objects.retain(|_, object| object.strong_count() > 0);
+        self.threshold
+            .set((objects.len() * self.growth.get()).max(MIN_THRESHOLD));
+        freed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::Interpreter;
+    use crate::test_support::{interpreter_on, on_each_backend, run, SharedBuffer};
+    use crate::typer::Typer;
+    use std::rc::Rc;
+
+    on_each_backend!(frees_cycles_and_keeps_what_is_reachable, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "class Node { init() { this.me = this; } }
+             var cycle = Node();
+             var kept = Node();
+             kept.list = [kept];
+             fun churn() {
+                 for (var i = 0; i < 10; i = i + 1) {
+                     var node = Node();
+                     var map = {\"node\": node};
+                     node.map = map;
+                     fun again() { return again; }
+                     node.callback = node.init;
+                 }
+             }
+             churn();",
+        );
+        let cycle = match interpreter.get_global("cycle") {
+            Some(Typer::Instance(instance)) => Rc::downgrade(&instance),
+            _ => unreachable!(),
+        };
+        run(&interpreter, "cycle = nil;");
+        assert!(cycle.upgrade().is_some(), "the cycle keeps itself alive");
+
+        assert!(interpreter.collect_garbage() > 10);
+        assert!(cycle.upgrade().is_none());
+        assert_eq!(interpreter.collect_garbage(), 0);
+
+        let output = SharedBuffer::default();
+        interpreter.set_output(output.clone());
+        run(&interpreter, "print kept.list[0].me == kept;");
+        assert_eq!(output.contents(), "true\n");
+    });
+
+    on_each_backend!(values_held_outside_the_heap_pin_their_cycles, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "class Node { init() { this.me = this; } } var cycle = Node();",
+        );
+        let held = interpreter.get_global("cycle").unwrap();
+        let cycle = match &held {
+            Typer::Instance(instance) => Rc::downgrade(instance),
+            _ => unreachable!(),
+        };
+        run(&interpreter, "cycle = nil;");
+
+        interpreter.collect_garbage();
+        assert!(cycle.upgrade().is_some());
+        drop(held);
+        assert!(interpreter.collect_garbage() > 0);
+        assert!(cycle.upgrade().is_none());
+    });
+
+    on_each_backend!(stress_mode_changes_nothing_scripts_see, |backend| {
+        let source = "
+            class Shape {
+                init(name) { this.name = name; }
+                describe() { return this.name + \" with area \" + to_string(this.area()); }
+            }
+            class Square < Shape {
+                init(side) { super.init(\"square\"); this.side = side; }
+                area() { return this.side * this.side; }
+            }
+            fun counter() {
+                var count = 0;
+                fun next() { count = count + 1; return count; }
+                return next;
+            }
+            var next = counter();
+            var shapes = [];
+            for (var i = 1; i <= 3; i = i + 1) push(shapes, Square(i));
+            var seen = {};
+            for (var i = 0; i < length(shapes); i = i + 1) {
+                var shape = shapes[i];
+                seen[shape.describe()] = [next(), split(\"a,b\", \",\")];
+            }
+            print seen;
+            print [counter()(), next(), shapes[2].describe()];";
+
+        let outputs: Vec<String> = [false, true]
+            .iter()
+            .map(|&stress| {
+                let output = SharedBuffer::default();
+                let interpreter = Interpreter::with_output(output.clone());
+                interpreter.set_backend(backend);
+                interpreter.set_gc_stress(stress);
+                run(&interpreter, source);
+                output.contents()
+            })
+            .collect();
+        assert_eq!(outputs[0], outputs[1]);
+    });
+
+    #[test]
+    fn collects_as_the_heap_grows() {
+        let interpreter = Interpreter::new();
+        interpreter.set_gc_growth(4);
+        run(
+            &interpreter,
+            "class Node {}
+             for (var i = 0; i < 5000; i = i + 1) { var node = Node(); node.me = node; }",
+        );
+        // collections already ran along the way, so fewer cycles are left
+        let left = interpreter.collect_garbage();
+        assert!(left > 0 && left < 5000, "{} cycles left", left);
+    }
+}
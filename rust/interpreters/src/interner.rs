@@ -0,0 +1,193 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+/// An interned string: identifiers, string literals and string values all
+/// become one. Equal strings share one allocation, so comparing or hashing
+/// a `Symbol` only looks at the pointer, never at the bytes.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Returns the one `Symbol` for `string` on this thread.
+pub fn intern(string: &str) -> Symbol {
+    INTERNER.with(|interner| interner.borrow_mut().intern(string))
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+// strings no longer in use are dropped from the table when it has doubled
+const MIN_SWEEP: usize = 1024;
+
+/// Every symbol alive on this thread, by a hash of its contents. Entries are
+/// weak, so strings built at runtime don't pile up.
+#[derive(Default)]
+struct Interner {
+    buckets: HashMap<u64, Vec<Weak<str>>>,
+    len: usize,
+    sweep_at: usize,
+}
+
+impl Interner {
+    fn intern(&mut self, string: &str) -> Symbol {
+        let mut hasher = DefaultHasher::new();
+        string.hash(&mut hasher);
+        let bucket = self.buckets.entry(hasher.finish()).or_default();
+        for entry in bucket.iter() {
+            if let Some(existing) = entry.upgrade() {
+                if &*existing == string {
+                    return Symbol(existing);
+                }
+            }
+        }
+
+        let symbol: Rc<str> = Rc::from(string);
+        bucket.push(Rc::downgrade(&symbol));
+        self.len += 1;
+        if self.len >= self.sweep_at.max(MIN_SWEEP) {
+            self.sweep();
+        }
+        Symbol(symbol)
+    }
+
+    fn sweep(&mut self) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|entry| entry.strong_count() > 0);
+            !bucket.is_empty()
+        });
+        self.len = self.buckets.values().map(Vec::len).sum();
+        self.sweep_at = self.len * 2;
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8 as usize).hash(state);
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+/// Alphabetical, as scripts compare strings.
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.0.cmp(&other.0)
+        }
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        intern(string)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(string: String) -> Self {
+        intern(&string)
+    }
+}
+
+impl From<Cow<'_, str>> for Symbol {
+    fn from(string: Cow<'_, str>) -> Self {
+        intern(&string)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_strings_share_a_symbol() {
+        let name = intern("counter");
+        let built = intern(&format!("count{}", "er"));
+        assert_eq!(name, built);
+        assert!(Rc::ptr_eq(&name.0, &built.0));
+        assert_ne!(name, intern("count"));
+        assert_eq!(name, "counter");
+        assert!(intern("apple") < intern("banana"));
+    }
+
+    #[test]
+    fn unused_strings_are_dropped() {
+        INTERNER.with(|interner| interner.borrow_mut().sweep());
+        let kept = intern("kept");
+        for i in 0..5000 {
+            intern(&i.to_string());
+        }
+        let live = INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            interner.sweep();
+            interner.len
+        });
+        assert!(live < 10, "{} strings still interned", live);
+        assert_eq!(kept, intern("kept"));
+    }
+}
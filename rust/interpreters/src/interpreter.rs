@@ -1,225 +1,1183 @@
+use crate::arithmetic;
+use crate::chunk::Function;
+use crate::compiler::Compiler;
 use crate::environment::Environment;
-use crate::error::Error;
-use crate::expr::Expr;
-use crate::expr::Visitor;
+use crate::error::{Error, ErrorCode, Frame};
+use crate::expr::{Depth, Expr};
+use crate::gc::{address, Heap, Trace};
+use crate::interner::intern;
+use crate::lox_class::{LoxClass, LoxInstance};
+use crate::lox_map::{LoxMap, MapKey};
+use crate::native::NativeFunction;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stdlib;
 use crate::stmt::Stmt;
 use crate::token::Literal;
-use crate::token::{Token, TokenType::*};
-use crate::typer::Typer;
-use std::cell::RefCell;
+use crate::token::{Span, Token, TokenType::*};
+use crate::typer::{NumberMode, Typer, Value};
+use crate::vm::Vm;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use std::rc::Rc;
 
-fn stringify(value: &Typer) -> String {
-    value.to_string()
+pub fn evaluate(value: &Expr, interpreter: &Interpreter) -> Result<Typer, Error> {
+    value.visit(interpreter)
 }
 
-pub fn evaluate(value: Expr) -> Result<Typer, Error> {
-    value.visit()
+pub fn is_truthy(val: &Typer) -> bool {
+    val.is_truthy()
 }
 
-fn check_num_operand(right: &Typer, operation: &Token) -> Result<Typer, Error> {
-    match right {
-        Typer::Number(num) => Ok(Typer::Number(num.clone())),
-        _ => Err(Error::RuntimeError {
-            token: Some(operation.clone()),
-            message: String::from(
-                "Mismatched unary operation. Cannot perform operation on the following.",
-            ),
-        }),
+/// Checks that `index` is an int addressing one of `len` items.
+fn list_index(index: &Typer, len: usize, bracket: &Token) -> Result<usize, Error> {
+    let err = |code, message: String| Error::RuntimeError {
+        token: Some(bracket.clone()),
+        code,
+        message,
+        trace: Vec::new(),
+    };
+    match index {
+        Typer::Int(_) | Typer::BigInt(_) => match index.as_int() {
+            Some(num) if num >= 0 && (num as u64) < len as u64 => Ok(num as usize),
+            _ => Err(err(
+                ErrorCode::IndexOutOfBounds,
+                format!(
+                    "Index {} is out of bounds for a list of length {}.",
+                    index, len
+                ),
+            )),
+        },
+        other => Err(err(
+            ErrorCode::TypeMismatch,
+            format!("List index must be an int but got {}.", other.type_name()),
+        )),
+    }
+}
+
+pub(crate) fn map_key(key: &Typer, token: &Token) -> Result<MapKey, Error> {
+    MapKey::from_typer(key).ok_or_else(|| Error::RuntimeError {
+        token: Some(token.clone()),
+        code: ErrorCode::TypeMismatch,
+        message: format!(
+            "Map keys must be strings, numbers or booleans but got {}.",
+            match key {
+                Typer::Number(_) => "NaN",
+                other => other.type_name(),
+            }
+        ),
+        trace: Vec::new(),
+    })
+}
+
+fn not_indexable(object: &Typer, bracket: &Token) -> Error {
+    Error::RuntimeError {
+        token: Some(bracket.clone()),
+        code: ErrorCode::TypeMismatch,
+        message: format!("Can't index into a {}.", object.type_name()),
+        trace: Vec::new(),
     }
 }
 
-fn is_truthy(val: &Typer) -> bool {
-    match val {
-        Typer::Boolean(b) => b.clone(),
-        Typer::Nil => false,
-        _ => true,
+/// Reads `object[index]` from a list or map.
+pub(crate) fn get_index(object: &Typer, index: &Typer, bracket: &Token) -> Result<Typer, Error> {
+    match object {
+        Typer::List(items) => {
+            let items = items.borrow();
+            let index = list_index(index, items.len(), bracket)?;
+            Ok(items[index].clone())
+        }
+        Typer::Map(map) => {
+            let key = map_key(index, bracket)?;
+            match map.borrow().get(&key) {
+                Some(value) => Ok(value.clone()),
+                None => Err(Error::RuntimeError {
+                    token: Some(bracket.clone()),
+                    code: ErrorCode::KeyNotFound,
+                    message: format!("Key '{}' is not in the map.", index),
+                    trace: Vec::new(),
+                }),
+            }
+        }
+        _ => Err(not_indexable(object, bracket)),
     }
 }
 
-fn is_equal(left: Typer, right: Typer, ops: Token) -> Result<bool, Error> {
-    let err = |s: &str| Error::RuntimeError {
-        token: Some(ops.clone()),
-        message: String::from(s),
-    };
-    match (left, right) {
-        (Typer::Nil, Typer::Nil) => Ok(true),
-        (Typer::Nil, _) => Ok(false),
-        (Typer::Number(left), Typer::Number(right)) => Ok(left == right),
-        (Typer::Str(left), Typer::Str(right)) => Ok(left == right),
-        (Typer::Boolean(left), Typer::Boolean(right)) => Ok(left == right),
-        (_, _) => Err(err("Mismatched types, cannot compare the two operands")),
+/// Stores `value` at `object[index]` and hands it back.
+pub(crate) fn set_index(
+    object: &Typer,
+    index: &Typer,
+    value: Typer,
+    bracket: &Token,
+) -> Result<Typer, Error> {
+    match object {
+        Typer::List(items) => {
+            let mut items = items.borrow_mut();
+            let index = list_index(index, items.len(), bracket)?;
+            items[index] = value.clone();
+            Ok(value)
+        }
+        Typer::Map(map) => {
+            let key = map_key(index, bracket)?;
+            map.borrow_mut().insert(key, value.clone());
+            Ok(value)
+        }
+        _ => Err(not_indexable(object, bracket)),
     }
 }
 
 impl Expr {
-    pub fn visit_binary_expr(self, left: Expr, ops: Token, right: Expr) -> Result<Typer, Error> {
-        let left = evaluate(left)?;
-        let right = evaluate(right)?;
+    pub fn visit_binary_expr(
+        &self,
+        left: &Expr,
+        ops: &Token,
+        right: &Expr,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let left = evaluate(left, interpreter)?;
+        let right = evaluate(right, interpreter)?;
+        // the comma operator only keeps its right operand
+        if ops.token_type == COMMA {
+            return Ok(right);
+        }
+        arithmetic::binary(ops, left, right)
+    }
+
+    pub fn visit_call(
+        &self,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let call_site = callee.span().to(paren.span);
+        let callee = evaluate(callee, interpreter)?;
+
+        let mut values = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            values.push(evaluate(argument, interpreter)?);
+        }
 
-        let err = |s: &str| Error::RuntimeError {
-            token: Some(ops.clone()),
-            message: String::from(s),
+        let err = |code, message: String| Error::RuntimeError {
+            token: Some(paren.clone()),
+            code,
+            message,
+            trace: Vec::new(),
         };
 
-        match ops.token_type {
-            PLUS => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => Ok(Typer::Number(left + right)),
-                (Typer::Str(left), Typer::Str(right)) => {
-                    Ok(Typer::Str(format!("{}{}", left, right)))
+        interpreter.check_depth(paren)?;
+        match callee {
+            Typer::Callable(function) => {
+                if values.len() != function.arity() {
+                    return Err(err(
+                        ErrorCode::ArityMismatch,
+                        format!(
+                            "Expected {} arguments but got {}.",
+                            function.arity(),
+                            values.len()
+                        ),
+                    ));
                 }
-                (Typer::Str(left), Typer::Number(right)) => {
-                    Ok(Typer::Str(format!("{}{}", left, right)))
-                }
-                (Typer::Str(left), Typer::Boolean(right)) => {
-                    Ok(Typer::Str(format!("{}{}", left, right)))
-                }
-                _ => Err(err("Mismatched types. Cannot add the two operands")),
-            },
-            MINUS => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => Ok(Typer::Number(left - right)),
-                _ => Err(err("Mismatched types. Cannot subtract the two operands")),
-            },
-            SLASH => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => {
-                    if right == 0.0 {
-                        return Err(err(
-                            "Divide by zero error. The denominator is equal to zero!",
-                        ));
-                    }
-                    Ok(Typer::Number(left / right))
+                interpreter.in_frame(function.name(), call_site, || {
+                    function
+                        .call(interpreter, values)
+                        .map_err(|error| error.or_at(paren))
+                })
+            }
+            Typer::Class(class) => {
+                if values.len() != class.arity() {
+                    return Err(err(
+                        ErrorCode::ArityMismatch,
+                        format!(
+                            "Expected {} arguments but got {}.",
+                            class.arity(),
+                            values.len()
+                        ),
+                    ));
                 }
-                _ => Err(err("Mismatched types. Cannot subtract the two operands")),
-            },
-            STAR => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => Ok(Typer::Number(left * right)),
-                _ => Err(err("Mismatched types. Cannot multiply the two operands")),
-            },
-            GREATER => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => Ok(Typer::Boolean(left > right)),
-                (Typer::Str(left), Typer::Str(right)) => Ok(Typer::Boolean(left > right)),
-                _ => Err(err("Mismatched types. Cannot compare the two operands")),
-            },
-            GREATER_EQUAL => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => Ok(Typer::Boolean(left >= right)),
-                (Typer::Str(left), Typer::Str(right)) => Ok(Typer::Boolean(left >= right)),
-                _ => Err(err("Mismatched types. Cannot compare the two operands")),
-            },
-            LESS => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => Ok(Typer::Boolean(left < right)),
-                (Typer::Str(left), Typer::Str(right)) => Ok(Typer::Boolean(left < right)),
-                _ => Err(err("Mismatched types. Cannot compare the two operands")),
-            },
-            LESS_EQUAL => match (left, right) {
-                (Typer::Number(left), Typer::Number(right)) => Ok(Typer::Boolean(left <= right)),
-                (Typer::Str(left), Typer::Str(right)) => Ok(Typer::Boolean(left <= right)),
-                _ => Err(err("Mismatched types. Cannot compare the two operands")),
-            },
-            BANG_EQUAL => match is_equal(left, right, ops) {
-                Ok(b) => Ok(Typer::Boolean(!b)),
-                Err(err) => Err(err),
-            },
-            EQUAL_EQUAL => match is_equal(left, right, ops) {
-                Ok(b) => Ok(Typer::Boolean(b)),
-                Err(err) => Err(err),
-            },
+                interpreter.in_frame(&class.name, call_site, || {
+                    LoxClass::instantiate(&class, interpreter, values)
+                })
+            }
             _ => Err(err(
-                "Cannot fathom the binary operation. Check your code again",
+                ErrorCode::NotCallable,
+                String::from("Can only call functions and classes."),
             )),
         }
     }
 
-    pub fn visit_grouping(&self, expr: Expr) -> Result<Typer, Error> {
-        evaluate(expr)
+    pub fn visit_get(
+        &self,
+        object: &Expr,
+        name: &Token,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        match evaluate(object, interpreter)? {
+            Typer::Instance(instance) => LoxInstance::get(&instance, name, interpreter),
+            _ => Err(Error::RuntimeError {
+                token: Some(name.clone()),
+                code: ErrorCode::NotAnInstance,
+                message: String::from("Only instances have properties."),
+                trace: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn visit_set(
+        &self,
+        object: &Expr,
+        name: &Token,
+        value: &Expr,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        match evaluate(object, interpreter)? {
+            Typer::Instance(instance) => {
+                let value = evaluate(value, interpreter)?;
+                instance.borrow_mut().set(name, value.clone());
+                Ok(value)
+            }
+            _ => Err(Error::RuntimeError {
+                token: Some(name.clone()),
+                code: ErrorCode::NotAnInstance,
+                message: String::from("Only instances have fields."),
+                trace: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn visit_super(
+        &self,
+        keyword: &Token,
+        method: &Token,
+        depth: &Depth,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        // `this` is always bound one scope inside the one holding `super`
+        let this = Token::new(THIS, "this", None, keyword.line);
+        let superclass = interpreter.look_up_variable(keyword, depth)?;
+        let instance =
+            interpreter.look_up_variable(&this, &Depth::new(depth.get().map(|d| d - 1)))?;
+
+        let found = match superclass {
+            Typer::Class(superclass) => superclass.find_method(&method.lexeme).cloned(),
+            _ => None,
+        };
+        match (found, instance) {
+            (Some(found), Typer::Instance(instance)) => {
+                Ok(Typer::Callable(found.bind(interpreter, instance)))
+            }
+            _ => Err(Error::RuntimeError {
+                token: Some(method.clone()),
+                code: ErrorCode::UndefinedProperty,
+                message: format!("Undefined property '{}'.", method.lexeme),
+                trace: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn visit_this(
+        &self,
+        keyword: &Token,
+        depth: &Depth,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        interpreter.look_up_variable(keyword, depth)
     }
 
-    pub fn visit_literal(&self, lit: Literal) -> Result<Typer, Error> {
+    pub fn visit_index(
+        &self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let object = evaluate(object, interpreter)?;
+        let index = evaluate(index, interpreter)?;
+        get_index(&object, &index, bracket)
+    }
+
+    pub fn visit_index_set(
+        &self,
+        object: &Expr,
+        bracket: &Token,
+        index: &Expr,
+        value: &Expr,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let object = evaluate(object, interpreter)?;
+        let index = evaluate(index, interpreter)?;
+        let value = evaluate(value, interpreter)?;
+        set_index(&object, &index, value, bracket)
+    }
+
+    pub fn visit_list(&self, elements: &[Expr], interpreter: &Interpreter) -> Result<Typer, Error> {
+        let mut items = Vec::with_capacity(elements.len());
+        for element in elements {
+            items.push(evaluate(element, interpreter)?);
+        }
+        Ok(Typer::List(interpreter.alloc(RefCell::new(items))))
+    }
+
+    pub fn visit_map(
+        &self,
+        entries: &[(Expr, Token, Expr)],
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let mut map = LoxMap::new();
+        for (key, colon, value) in entries {
+            let key = map_key(&evaluate(key, interpreter)?, colon)?;
+            map.insert(key, evaluate(value, interpreter)?);
+        }
+        Ok(Typer::Map(interpreter.alloc(RefCell::new(map))))
+    }
+
+    pub fn visit_grouping(&self, expr: &Expr, interpreter: &Interpreter) -> Result<Typer, Error> {
+        evaluate(expr, interpreter)
+    }
+
+    /// Short-circuits and hands back whichever operand decided the result,
+    /// so `nil or "default"` evaluates to `"default"` rather than `true`.
+    pub fn visit_logical(
+        &self,
+        left: &Expr,
+        operator: &Token,
+        right: &Expr,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let left = evaluate(left, interpreter)?;
+
+        match operator.token_type {
+            OR if is_truthy(&left) => Ok(left),
+            AND if !is_truthy(&left) => Ok(left),
+            _ => evaluate(right, interpreter),
+        }
+    }
+
+    pub fn visit_literal(&self, lit: &Option<Literal>) -> Result<Typer, Error> {
         match lit {
-            Literal::Bool(b) => Ok(Typer::Boolean(b.clone())),
-            Literal::Str(b) => Ok(Typer::Str(b.clone())),
-            Literal::Number(b) => Ok(Typer::Number(b.clone())),
-            Literal::Nil => Ok(Typer::Nil),
+            Some(Literal::Bool(b)) => Ok(Typer::Boolean(*b)),
+            Some(Literal::Str(b)) => Ok(Typer::Str(b.clone())),
+            Some(Literal::Int(b)) => Ok(Typer::Int(*b)),
+            Some(Literal::Number(b)) => Ok(Typer::Number(*b)),
+            Some(Literal::BigInt(b)) => Ok(Typer::BigInt(Rc::clone(b))),
+            Some(Literal::Decimal(b)) => Ok(Typer::Decimal(Rc::clone(b))),
+            Some(Literal::Nil) | None => Ok(Typer::Nil),
         }
     }
 
-    pub fn visit_unary(&self, operator: Token, operand: Expr) -> Result<Typer, Error> {
-        let right = evaluate(operand)?;
+    pub fn visit_unary(
+        &self,
+        operator: &Token,
+        operand: &Expr,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let right = evaluate(operand, interpreter)?;
         match operator.token_type {
             BANG => Ok(Typer::Boolean(!is_truthy(&right))),
-            MINUS => {
-                check_num_operand(&right, &operator)?;
-                match right {
-                    Typer::Number(num) => Ok(Typer::Number(-num)),
-                    _ => Ok(Typer::Nil),
-                }
-            }
+            MINUS => arithmetic::negate(operator, right),
             _ => Ok(Typer::Nil),
         }
     }
 
     pub fn visit_ternary(
         &self,
-        condition: Expr,
-        first: Expr,
-        second: Expr,
-        operator: Token,
+        condition: &Expr,
+        first: &Expr,
+        second: &Expr,
+        operator: &Token,
+        interpreter: &Interpreter,
     ) -> Result<Typer, Error> {
-        let condition = evaluate(condition)?;
-        let first = evaluate(first)?;
-        let second = evaluate(second)?;
+        let condition = evaluate(condition, interpreter)?;
+        let first = evaluate(first, interpreter)?;
+        let second = evaluate(second, interpreter)?;
 
         match condition {
             Typer::Boolean(b) => Ok(if b { first } else { second }),
             Typer::Nil => Ok(second),
             _ => Err(Error::RuntimeError {
                 token: Some(operator.clone()),
-                message: format!("ternary operation failed."),
+                code: ErrorCode::TypeMismatch,
+                message: String::from("ternary operation failed."),
+                trace: Vec::new(),
             }),
         }
     }
 
-    pub fn visit_assign(&self, name: Expr, token: Token) -> Result<Typer, Error> {
-        Ok(Typer::Nil)
+    pub fn visit_assign(
+        &self,
+        name: &Token,
+        value: &Expr,
+        depth: &Depth,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        let value = evaluate(value, interpreter)?;
+        match depth.get() {
+            Some(distance) => Environment::assign_at(
+                &interpreter.get_environment(),
+                distance,
+                name,
+                value.clone(),
+            ),
+            None => interpreter
+                .globals
+                .borrow_mut()
+                .assign(name, value.clone())?,
+        }
+        Ok(value)
     }
 
-    pub fn visit_variable(&self, var: Token) -> Result<Typer, Error> {
-        Ok(Typer::Nil)
+    pub fn visit_variable(
+        &self,
+        var: &Token,
+        depth: &Depth,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        interpreter.look_up_variable(var, depth)
+    }
+}
+
+/// Which engine runs parsed code. Both share globals, natives and output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Walks the syntax tree directly.
+    #[default]
+    TreeWalk,
+    /// Compiles to bytecode first and runs that on a stack VM.
+    Bytecode,
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tree-walk" => Some(Backend::TreeWalk),
+            "bytecode" => Some(Backend::Bytecode),
+            _ => None,
+        }
+    }
+
+    /// How many calls may be in progress at once before a script is stopped
+    /// with a stack overflow, counting calls into natives. Each tree-walker
+    /// call recurses through several native frames, so that backend stops
+    /// early enough for the 2 MiB stack of a spawned thread, even in a debug
+    /// build; the VM keeps its frames on the heap.
+    pub fn max_call_depth(self) -> usize {
+        match self {
+            Backend::TreeWalk => 64,
+            Backend::Bytecode => 1024,
+        }
     }
 }
 
 pub struct Interpreter {
-    pub environment: RefCell<Environment>,
+    pub globals: Rc<RefCell<Environment>>,
+    pub environment: RefCell<Rc<RefCell<Environment>>>,
+    // where `print` writes; stdout unless the embedder says otherwise
+    output: RefCell<Box<dyn Write>>,
+    numbers: Cell<NumberMode>,
+    // the calls being run right now, outermost first
+    frames: RefCell<Vec<Frame>>,
+    backend: Cell<Backend>,
+    pub(crate) vm: Vm,
+    heap: Heap,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {
-            environment: RefCell::new(Environment::new_empty_env()),
+        let globals = Rc::new(RefCell::new(Environment::new_empty_env()));
+        let interpreter = Self {
+            environment: RefCell::new(Rc::clone(&globals)),
+            globals,
+            output: RefCell::new(Box::new(io::stdout())),
+            numbers: Cell::new(NumberMode::Float),
+            frames: RefCell::new(Vec::new()),
+            backend: Cell::new(Backend::TreeWalk),
+            vm: Vm::default(),
+            heap: Heap::default(),
+        };
+        interpreter.track(&interpreter.globals);
+        stdlib::install(&interpreter);
+        interpreter
+    }
+
+    pub fn with_output(output: impl Write + 'static) -> Self {
+        let interpreter = Self::new();
+        interpreter.set_output(output);
+        interpreter
+    }
+
+    /// Sends everything `print` writes from now on to `output`.
+    pub fn set_output(&self, output: impl Write + 'static) {
+        self.output.replace(Box::new(output));
+    }
+
+    /// Defines a global `name` that runs `function` when scripts call it.
+    pub fn define_native(
+        &self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&Interpreter, Vec<Typer>) -> Result<Typer, Error> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, function);
+        self.globals
+            .borrow_mut()
+            .define(intern(name), Some(Typer::Callable(Rc::new(native))));
+    }
+
+    /// Gives scripts `read_line`, `read_file`, `write_file` and `file_exists`.
+    /// Only do this for scripts you trust.
+    pub fn enable_io(&self) {
+        stdlib::install_io(self);
+    }
+
+    /// Chooses what number literals in code run from now on evaluate to.
+    pub fn set_number_mode(&self, numbers: NumberMode) {
+        self.numbers.set(numbers);
+    }
+
+    pub fn number_mode(&self) -> NumberMode {
+        self.numbers.get()
+    }
+
+    /// Chooses how code run from now on is executed.
+    pub fn set_backend(&self, backend: Backend) {
+        self.backend.set(backend);
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend.get()
+    }
+
+    /// After a collection, waits for the heap to grow to `factor` times
+    /// what survived before collecting again. Defaults to 2.
+    pub fn set_gc_growth(&self, factor: usize) {
+        self.heap.set_growth(factor);
+    }
+
+    /// Collects garbage on every allocation. Slow; meant for tests.
+    pub fn set_gc_stress(&self, stress: bool) {
+        self.heap.set_stress(stress);
+    }
+
+    /// Frees every object that is only kept alive by a reference cycle and
+    /// returns how many there were.
+    pub fn collect_garbage(&self) -> usize {
+        let mut roots = vec![address(&self.globals)];
+        if let Ok(environment) = self.environment.try_borrow() {
+            roots.push(address(&environment));
+        }
+        self.vm.roots(&mut roots);
+        self.heap.collect(&roots)
+    }
+
+    /// Moves `object` onto the heap, where the collector can see it.
+    pub(crate) fn alloc<T: Trace + 'static>(&self, object: T) -> Rc<T> {
+        let object = Rc::new(object);
+        self.track(&object);
+        object
+    }
+
+    pub(crate) fn track<T: Trace + 'static>(&self, object: &Rc<T>) {
+        if self.heap.track(object) {
+            self.collect_garbage();
+        }
+    }
+
+    /// Tracks a list or map built outside the interpreter, such as one a
+    /// native function returned.
+    pub(crate) fn track_value(&self, value: &Typer) {
+        match value {
+            Typer::List(items) => self.track(items),
+            Typer::Map(map) => self.track(map),
+            _ => {}
+        }
+    }
+
+    /// Runs `call` with a frame for `function` on the call stack, so errors
+    /// raised inside it carry a trace.
+    pub(crate) fn in_frame(
+        &self,
+        function: &str,
+        call_site: Span,
+        call: impl FnOnce() -> Result<Typer, Error>,
+    ) -> Result<Typer, Error> {
+        self.push_frame(function, call_site);
+        let result = call().map_err(|error| self.traced(error));
+        self.pop_frame();
+        result
+    }
+
+    pub(crate) fn push_frame(&self, function: &str, call_site: Span) {
+        self.frames.borrow_mut().push(Frame {
+            function: String::from(function),
+            call_site,
+        });
+    }
+
+    pub(crate) fn pop_frame(&self) {
+        self.frames.borrow_mut().pop();
+    }
+
+    pub(crate) fn frame_depth(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    /// Drops the frames of calls an error unwound through without returning.
+    pub(crate) fn unwind_frames(&self, depth: usize) {
+        self.frames.borrow_mut().truncate(depth);
+    }
+
+    /// Gives `error` the current call stack as its trace if it has none yet.
+    pub(crate) fn traced(&self, error: Error) -> Error {
+        error.with_trace(&self.frames.borrow())
+    }
+
+    /// Fails with a stack overflow at `paren` once the backend's
+    /// `max_call_depth` calls are in progress.
+    pub(crate) fn check_depth(&self, paren: &Token) -> Result<(), Error> {
+        if self.frame_depth() < self.backend().max_call_depth() {
+            return Ok(());
+        }
+        Err(Error::RuntimeError {
+            token: Some(paren.clone()),
+            code: ErrorCode::StackOverflow,
+            message: String::from("Stack overflow."),
+            trace: Vec::new(),
+        })
+    }
+
+    pub fn write_line(&self, text: &str) -> io::Result<()> {
+        writeln!(self.output.borrow_mut(), "{}", text)
+    }
+
+    pub fn interpret(&self, statements: Vec<Stmt>) -> Result<(), Error> {
+        match self.backend() {
+            Backend::TreeWalk => {
+                for statement in &statements {
+                    self.execute(statement)?;
+                }
+            }
+            Backend::Bytecode => {
+                self.vm.run(self, Compiler::compile(&statements)?)?;
+            }
         }
+        Ok(())
+    }
+
+    /// Runs a script that was compiled ahead of time, on the VM whatever the
+    /// backend.
+    pub(crate) fn run_compiled(&self, script: Rc<Function>) -> Result<(), Error> {
+        self.vm.run(self, script)?;
+        Ok(())
     }
 
-    pub fn interpret(&self, statements: Vec<Stmt>) -> Result<String, Error> {
-        for statement in statements {
-            self.execute(statement)?;
+    /// Evaluates a single expression such as `price * 2` against the current
+    /// globals and hands back its value.
+    pub fn eval_expression(&self, source: &str) -> Result<Value, Error> {
+        let (tokens, errors) =
+            Scanner::with_numbers(String::from(source), self.number_mode()).scan_tokens();
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error);
         }
-        Ok("".to_string())
+        let expr = Parser::new(tokens).parse_expression()?;
+        Resolver::new().resolve_expression(&expr)?;
+        match self.backend() {
+            Backend::TreeWalk => evaluate(&expr, self),
+            Backend::Bytecode => self.vm.run(self, Compiler::compile_expression(&expr)?),
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get_local(name)
     }
 
-    pub fn set_environment(&self, env: Environment) -> Result<(), Error> {
+    /// Defines or overwrites the global `name`.
+    pub fn set_global(&self, name: &str, value: impl Into<Value>) {
+        let value = value.into();
+        self.track_value(&value);
+        self.globals.borrow_mut().define(intern(name), Some(value));
+    }
+
+    pub fn set_environment(&self, env: Rc<RefCell<Environment>>) -> Result<(), Error> {
         self.environment.replace(env);
         Ok(())
     }
 
-    pub fn get_environment(&self) -> Environment {
-        *self.environment.borrow()
+    pub fn get_environment(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.environment.borrow())
+    }
+
+    /// Runs `statements` inside `environment`, restoring the current scope
+    /// afterwards even when a statement fails or returns.
+    pub fn execute_block(
+        &self,
+        statements: &[Stmt],
+        environment: Environment,
+    ) -> Result<(), Error> {
+        let previous = self.get_environment();
+        self.set_environment(self.alloc(RefCell::new(environment)))?;
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+        self.set_environment(previous)?;
+        result
+    }
+
+    /// Resolved variables are read from the exact scope the resolver found
+    /// them in; anything unresolved is assumed to be a global.
+    pub fn look_up_variable(&self, name: &Token, depth: &Depth) -> Result<Typer, Error> {
+        match depth.get() {
+            Some(distance) => Environment::get_at(&self.get_environment(), distance, name),
+            None => self.globals.borrow().get(name),
+        }
     }
 
-    pub fn execute(&self, statement: Stmt) -> Result<(), Error> {
+    pub fn execute(&self, statement: &Stmt) -> Result<(), Error> {
         statement.visit(self)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::test_support::{global, interpreter_on, on_each_backend, parse, run, SharedBuffer};
+
+    on_each_backend!(if_else, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var a = 1; if (a > 0) a = \"pos\"; else a = \"neg\";",
+        );
+        assert_eq!(global(&interpreter, "a"), "pos");
+
+        let interpreter = interpreter_on(backend);
+        run(&interpreter, "var a = nil; if (a) a = 1; else { a = 2; }");
+        assert_eq!(global(&interpreter, "a"), "2");
+    });
+
+    on_each_backend!(while_loop, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(&interpreter, "var i = 0; while (i < 5) { i = i + 1; }");
+        assert_eq!(global(&interpreter, "i"), "5");
+    });
+
+    on_each_backend!(logical_short_circuit, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var a = nil or \"default\"; var b = 0 and 2; var c = false and a;",
+        );
+        assert_eq!(global(&interpreter, "a"), "default");
+        assert_eq!(global(&interpreter, "b"), "2");
+        assert_eq!(global(&interpreter, "c"), "false");
+
+        // the right operand must not run once the left one decides
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var x = 1; true or (x = 2); false and (x = 3);",
+        );
+        assert_eq!(global(&interpreter, "x"), "1");
+    });
+
+    on_each_backend!(functions_and_return, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "fun add(a, b) { return a + b; } var x = add(1, 2);",
+        );
+        assert_eq!(global(&interpreter, "x"), "3");
+
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } var x = fib(10);",
+        );
+        assert_eq!(global(&interpreter, "x"), "55");
+
+        // return unwinds out of nested blocks and loops
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "fun find() { for (var i = 0; i < 10; i = i + 1) { { if (i == 3) return i; } } } \
+             var x = find(); fun nothing() {} var y = nothing();",
+        );
+        assert_eq!(global(&interpreter, "x"), "3");
+        assert_eq!(global(&interpreter, "y"), "Nil");
+    });
+
+    on_each_backend!(closures, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "fun make_adder(n) { fun adder(x) { return x + n; } return adder; } \
+             var add_two = make_adder(2); var x = add_two(40);",
+        );
+        assert_eq!(global(&interpreter, "x"), "42");
+        assert_eq!(global(&interpreter, "add_two"), "<fn adder>");
+    });
+
+    on_each_backend!(shared_scopes, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(&interpreter, "var a = 1; { var b = 2; { a = a + b; } }");
+        assert_eq!(global(&interpreter, "a"), "3");
+
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "fun counter() { var i = 0; fun inc() { i = i + 1; return i; } return inc; } \
+             var c = counter(); c(); c(); var x = c(); \
+             var total = 0; fun add(n) { total = total + n; } add(2); add(3);",
+        );
+        assert_eq!(global(&interpreter, "x"), "3");
+        assert_eq!(global(&interpreter, "total"), "5");
+    });
+
+    on_each_backend!(resolved_scopes, |backend| {
+        // the closure keeps seeing the global it resolved to, even after a
+        // later local declaration with the same name
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var a = \"global\"; var first; var second; \
+             { fun show() { return a; } first = show(); var a = \"block\"; second = show(); }",
+        );
+        assert_eq!(global(&interpreter, "first"), "global");
+        assert_eq!(global(&interpreter, "second"), "global");
+    });
+
+    on_each_backend!(print_writes_to_output, |backend| {
+        let output = SharedBuffer::default();
+        let interpreter = Interpreter::with_output(output.clone());
+        interpreter.set_backend(backend);
+        interpreter
+            .interpret(parse("print 1 + 2; print \"a\" + \"b\"; print nil;"))
+            .unwrap();
+        assert_eq!(output.contents(), "3\nab\nNil\n");
+    });
+
+    on_each_backend!(host_reads_and_writes_values, |backend| {
+        let interpreter = interpreter_on(backend);
+        interpreter.set_global("price", 4.0);
+        interpreter.set_global("name", "tea");
+        interpreter
+            .interpret(parse(
+                "var total = price * 2; fun label() { return name + \"!\"; }",
+            ))
+            .unwrap();
+
+        assert_eq!(
+            interpreter.get_global("total").unwrap().as_number(),
+            Some(8.0)
+        );
+        assert!(interpreter.get_global("missing").is_none());
+        assert_eq!(
+            interpreter
+                .eval_expression("total + 1")
+                .unwrap()
+                .as_number(),
+            Some(9.0)
+        );
+        assert_eq!(
+            interpreter.eval_expression("label()").unwrap().as_str(),
+            Some("tea!")
+        );
+        assert!(interpreter.eval_expression("total +").is_err());
+        assert!(interpreter.eval_expression("total total").is_err());
+        assert!(interpreter.eval_expression("nope").is_err());
+    });
+
+    on_each_backend!(lists, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var xs = [1, \"two\", [3],]; var first = xs[0]; \
+             xs[1] = xs[2][0] + 1; var alias = xs; alias[0] = 10; var empty = [];",
+        );
+        assert_eq!(global(&interpreter, "xs"), "[10, 4, [3]]");
+        assert_eq!(global(&interpreter, "first"), "1");
+        assert_eq!(global(&interpreter, "empty"), "[]");
+
+        let code_of = |source: &str| match interpreter_on(backend).interpret(parse(source)) {
+            Err(Error::RuntimeError { code, token, .. }) => {
+                assert_eq!(token.unwrap().lexeme, "]");
+                code
+            }
+            _ => panic!("expected a runtime error for: {}", source),
+        };
+        assert_eq!(code_of("[1, 2][2];"), ErrorCode::IndexOutOfBounds);
+        assert_eq!(code_of("[1, 2][-1] = 0;"), ErrorCode::IndexOutOfBounds);
+        assert_eq!(code_of("[1, 2][0.5];"), ErrorCode::TypeMismatch);
+        assert_eq!(code_of("\"ab\"[0];"), ErrorCode::TypeMismatch);
+    });
+
+    on_each_backend!(maps, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var m = {\"name\": \"tea\", 1: true, false: nil,}; \
+             m[\"price\"] = 4; m[1] = m[\"price\"] * 2; var empty = {}; \
+             { var inner = {\"k\": {\"deep\": [1]}}; m[\"deep\"] = inner[\"k\"][\"deep\"][0]; }",
+        );
+        assert_eq!(
+            global(&interpreter, "m"),
+            "{name: tea, 1: 8, false: Nil, price: 4, deep: 1}"
+        );
+        assert_eq!(global(&interpreter, "empty"), "{}");
+
+        let code_of = |source: &str| match interpreter_on(backend).interpret(parse(source)) {
+            Err(Error::RuntimeError { code, .. }) => code,
+            _ => panic!("expected a runtime error for: {}", source),
+        };
+        assert_eq!(code_of("var m = {}; m[\"x\"];"), ErrorCode::KeyNotFound);
+        assert_eq!(code_of("var m = {}; m[[1]] = 1;"), ErrorCode::TypeMismatch);
+        assert_eq!(code_of("var m = {nil: 1};"), ErrorCode::TypeMismatch);
+    });
+
+    on_each_backend!(ints_and_floats, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var big = 9007199254740993 + 0; var half = 7 / 2; \
+             var whole = 7 ~/ 2; var neg = -7 ~/ 2; var rem = -7 % 3; var mixed = 1 + 0.5; \
+             var same = 1 == 1.0; var floats = 7.5 ~/ 2; var frac = 1.5 % 1;",
+        );
+        assert_eq!(global(&interpreter, "big"), "9007199254740993");
+        assert_eq!(global(&interpreter, "half"), "3.5");
+        assert_eq!(global(&interpreter, "whole"), "3");
+        assert_eq!(global(&interpreter, "neg"), "-3");
+        assert_eq!(global(&interpreter, "rem"), "-1");
+        assert_eq!(global(&interpreter, "mixed"), "1.5");
+        assert_eq!(global(&interpreter, "same"), "true");
+        assert_eq!(global(&interpreter, "floats"), "3");
+        assert_eq!(global(&interpreter, "frac"), "0.5");
+        assert_eq!(interpreter.get_global("whole").unwrap().as_int(), Some(3));
+        assert_eq!(interpreter.get_global("floats").unwrap().as_int(), None);
+
+        let code_of = |source: &str| match interpreter_on(backend).interpret(parse(source)) {
+            Err(Error::RuntimeError { code, .. }) => code,
+            _ => panic!("expected a runtime error for: {}", source),
+        };
+        assert_eq!(
+            code_of("9223372036854775807 + 1;"),
+            ErrorCode::IntegerOverflow
+        );
+        assert_eq!(
+            code_of("var min = -9223372036854775807 - 1; -min;"),
+            ErrorCode::IntegerOverflow
+        );
+        assert_eq!(
+            code_of("(-9223372036854775807 - 1) ~/ -1;"),
+            ErrorCode::IntegerOverflow
+        );
+        let min = interpreter_on(backend)
+            .eval_expression("(-9223372036854775807 - 1) % -1")
+            .unwrap();
+        assert_eq!(min.as_int(), Some(0));
+        assert_eq!(code_of("1 % 0;"), ErrorCode::DivisionByZero);
+        assert_eq!(code_of("1 ~/ 0.0;"), ErrorCode::DivisionByZero);
+    });
+
+    on_each_backend!(exact_numbers, |backend| {
+        let interpreter = interpreter_on(backend);
+        interpreter.set_number_mode(NumberMode::Exact);
+        let eval = |source: &str| interpreter.eval_expression(source).unwrap().to_string();
+        assert_eq!(eval("0.1 + 0.2"), "0.3");
+        assert_eq!(eval("0.1 * 3 == 0.3"), "true");
+        assert_eq!(eval("1.10 * 3"), "3.3");
+        assert_eq!(
+            eval("9223372036854775807 * 100 + 1"),
+            "922337203685477580701"
+        );
+        assert_eq!(
+            eval("100000000000000000001 > 100000000000000000000"),
+            "true"
+        );
+        assert_eq!(eval("10 / 4"), "2.5");
+        assert_eq!(eval("1 / 1024"), "0.0009765625");
+        // quotients that don't terminate are rounded to DIVISION_DIGITS
+        let third = eval("2 / 3");
+        assert_eq!(third.len(), 2 + crate::DIVISION_DIGITS as usize);
+        assert!(third.starts_with("0.666") && third.ends_with("667"));
+        assert_eq!(eval("2 / 3 == 2.0 / 3"), "true");
+        assert_eq!(eval("7 ~/ 2"), "3");
+        assert_eq!(eval("-7.5 ~/ 2"), "-3");
+        assert_eq!(eval("7.5 % 2"), "1.5");
+        assert_eq!(eval("-(0.5)"), "-0.5");
+        assert_eq!(eval("\"$\" + 1.50"), "$1.5");
+        assert_eq!(eval("type_of(1) + type_of(1.0)"), "intdecimal");
+        assert_eq!(eval("[1, 2][1]"), "2");
+        assert_eq!(eval("{2: \"two\"}[2.0]"), "two");
+        assert_eq!(eval("max(0.1, 0.25) + min(3, 2)"), "2.25");
+        assert_eq!(eval("len(\"abc\") + 0.5"), "3.5");
+        assert!(eval("1 / 3").starts_with("0.3333333333"));
+        assert!(interpreter.eval_expression("1 % 0").is_err());
+
+        // floats stay the default
+        let interpreter = interpreter_on(backend);
+        assert_eq!(
+            interpreter
+                .eval_expression("0.1 + 0.2")
+                .unwrap()
+                .to_string(),
+            "0.30000000000000004"
+        );
+    });
+
+    #[test]
+    fn number_literals() {
+        let scan = |source: &str| Scanner::new(String::from(source)).scan_tokens();
+        match &scan("42 4.5").0[..] {
+            [int, float, _] => {
+                assert!(matches!(int.literal, Some(Literal::Int(42))));
+                assert!(matches!(float.literal, Some(Literal::Number(n)) if n == 4.5));
+            }
+            tokens => panic!("unexpected tokens: {:?}", tokens),
+        }
+        match &scan("99999999999999999999").1[..] {
+            [Error::CompileTimeError { code, .. }] => assert_eq!(*code, ErrorCode::InvalidNumber),
+            other => panic!("expected an invalid number, got {:?}", other),
+        }
+        // a dot without a digit after it is left for the parser
+        let (tokens, _errors) = scan("1.");
+        assert!(matches!(tokens[0].literal, Some(Literal::Int(1))));
+        assert_eq!(tokens[1].token_type, DOT);
+    }
+
+    on_each_backend!(call_errors, |backend| {
+        let statements = parse("fun f(a) {}\nf(1, 2);");
+        match interpreter_on(backend).interpret(statements) {
+            Err(Error::RuntimeError {
+                token: Some(token),
+                code,
+                message,
+                ..
+            }) => {
+                assert_eq!(code, ErrorCode::ArityMismatch);
+                assert_eq!(token.lexeme, ")");
+                assert_eq!(token.line, 2);
+                assert_eq!(token.span, Span::new(18, 19, 2, 7));
+                assert_eq!(message, "Expected 1 arguments but got 2.");
+            }
+            _ => panic!("expected an arity error"),
+        }
+    });
+
+    on_each_backend!(runtime_errors_carry_the_call_stack, |backend| {
+        let statements = parse(
+            "fun inner(x) {\n  return x + nil;\n}\nfun outer() {\n  return inner(1);\n}\nouter();",
+        );
+        let interpreter = interpreter_on(backend);
+        let error = interpreter.interpret(statements).unwrap_err();
+        let trace: Vec<_> = error
+            .trace()
+            .iter()
+            .map(|frame| (frame.function.as_str(), frame.call_site.line))
+            .collect();
+        assert_eq!(trace, vec![("inner", 5), ("outer", 7)]);
+        assert_eq!(error.span().unwrap().line, 2);
+        assert_eq!(
+            error.trace()[0].call_site,
+            Span::new(58, 66, 5, 10),
+            "the call site spans the callee through the closing paren"
+        );
+
+        // the stack unwinds even when a call fails, and top-level errors
+        // have no trace
+        let error = interpreter.interpret(parse("nil + 1;")).unwrap_err();
+        assert!(error.trace().is_empty());
+
+        // natives and classes get frames too
+        let error = interpreter_on(backend)
+            .interpret(parse("class A { init() { sqrt(\"x\"); } }\nA();"))
+            .unwrap_err();
+        let names: Vec<_> = error.trace().iter().map(|f| f.function.as_str()).collect();
+        assert_eq!(names, vec!["sqrt", "A"]);
+    });
+
+    on_each_backend!(runaway_recursion_is_a_stack_overflow, |backend| {
+        let interpreter = interpreter_on(backend);
+        let error = interpreter
+            .interpret(parse("fun f(n) {\n  return f(n + 1);\n}\nf(0);"))
+            .unwrap_err();
+        assert_eq!(error.code(), Some(ErrorCode::StackOverflow));
+        assert_eq!(error.message(), "Stack overflow.");
+        assert_eq!(error.span().unwrap().line, 2);
+        assert_eq!(error.trace().len(), backend.max_call_depth());
+        assert_eq!(error.trace()[0].function, "f");
+
+        // the stack unwinds, so the next call starts from the bottom again
+        interpreter
+            .interpret(parse("fun g() { return 1; }\nvar a = g();"))
+            .unwrap();
+        assert_eq!(global(&interpreter, "a"), "1");
+    });
+
+    on_each_backend!(comma_operator, |backend| {
+        let output = SharedBuffer::default();
+        let interpreter = interpreter_on(backend);
+        interpreter.set_output(output.clone());
+        run(
+            &interpreter,
+            "var n = 0;
+             fun bump() { n = n + 1; return n; }
+             print (1, 2);
+             print (bump(), bump(), \"last\");
+             print n;",
+        );
+        assert_eq!(output.contents(), "2\nlast\n2\n");
+    });
+
+    on_each_backend!(classes, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "class Point { init(x, y) { this.x = x; this.y = y; } \
+               sum() { return this.x + this.y; } } \
+             var p = Point(1, 2); p.x = 10; var s = p.sum(); var m = p.sum;",
+        );
+        assert_eq!(global(&interpreter, "p"), "Point instance");
+        assert_eq!(global(&interpreter, "s"), "12");
+        assert_eq!(global(&interpreter, "m"), "<fn sum>");
+
+        // init always hands back the instance, even on a bare `return;`
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "class A { init() { this.a = 1; return; } } var a = A(); var b = a.init();",
+        );
+        assert_eq!(global(&interpreter, "b"), "A instance");
+    });
+
+    on_each_backend!(inheritance, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "class A { name() { return \"A\"; } greet() { return \"hi \" + this.name(); } } \
+             class B < A { name() { return \"B\" + super.name(); } } \
+             var g = B().greet();",
+        );
+        assert_eq!(global(&interpreter, "g"), "hi BA");
+    });
+
+    on_each_backend!(for_loop, |backend| {
+        let interpreter = interpreter_on(backend);
+        run(
+            &interpreter,
+            "var sum = 0; for (var i = 1; i <= 4; i = i + 1) sum = sum + i;",
+        );
+        assert_eq!(global(&interpreter, "sum"), "10");
+
+        // the loop variable is scoped to the desugared block
+        assert!(interpreter.get_global("i").is_none());
+    });
+
     // use super::Expr::*;
     // use super::*;
     // use crate::parser::Parser;
@@ -1,37 +1,66 @@
+// Errors hold the offending token and, at runtime, the call stack. They only
+// travel on the failure path, so they are passed by value rather than boxed.
+#![allow(clippy::result_large_err)]
+
+mod arithmetic;
 pub mod ast_printer;
+pub mod chunk;
+mod compiler;
+pub mod diagnostics;
+pub mod disassembler;
 mod environment;
 pub mod error;
 pub mod expr;
+mod gc;
+pub mod interner;
 pub mod interpreter;
+mod lox_class;
+mod lox_function;
+mod lox_map;
+pub mod native;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
+pub mod serializer;
+mod stdlib;
 mod stmt;
+#[cfg(test)]
+mod test_support;
 pub mod token;
-mod typer;
+pub mod typer;
+mod vm;
 
 use crate::error::Error;
 use core::cell::RefCell;
+use diagnostics::{ColorMode, Diagnostic, ErrorFormat};
+pub use interpreter::Backend;
 use interpreter::Interpreter;
 use parser::Parser;
+use resolver::Resolver;
 use scanner::Scanner;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
 use std::io::prelude::{Read, Write};
 use std::process;
-
-trait Throw<E> {
-    fn throw(&self, callback: impl Fn(&E) -> String);
-}
-
-impl<V, E> Throw<E> for Result<V, E> {
-    fn throw(&self, callback: impl Fn(&E) -> String) {
-        panic!("Error Type: {}", callback(self.as_ref().err().unwrap()));
-    }
-}
+use stmt::Stmt;
+pub use typer::{FromTyper, NumberMode, Value, DIVISION_DIGITS};
 
 pub struct Runner {
     pub had_error: RefCell<bool>,
     pub had_runtime_error: RefCell<bool>,
+    pub color: ColorMode,
+    pub error_format: ErrorFormat,
+    // kept across runs so the prompt remembers earlier lines
+    interpreter: Interpreter,
+    // what diagnostics quote from and point at
+    source: RefCell<String>,
+    file: RefCell<Option<String>>,
+}
+
+impl Default for Runner {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Runner {
@@ -39,34 +68,166 @@ impl Runner {
         Runner {
             had_error: RefCell::new(false),
             had_runtime_error: RefCell::new(false),
+            color: ColorMode::detect(),
+            error_format: ErrorFormat::Human,
+            interpreter: Interpreter::new(),
+            source: RefCell::new(String::new()),
+            file: RefCell::new(None),
         }
     }
 
-    pub fn run(&self, source: String) -> Result<(), Error> {
-        let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens()?;
+    /// Sends the output of `print` to `output` instead of stdout.
+    pub fn set_output(&self, output: impl Write + 'static) {
+        self.interpreter.set_output(output);
+    }
+
+    /// Makes a Rust function callable from scripts as a global named `name`.
+    pub fn define_native(
+        &self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&Interpreter, Vec<Value>) -> Result<Value, Error> + 'static,
+    ) {
+        self.interpreter.define_native(name, arity, function);
+    }
+
+    /// Lets scripts read stdin and files and write files. Off by default so
+    /// untrusted scripts stay sandboxed.
+    pub fn enable_io(&self) {
+        self.interpreter.enable_io();
+    }
+
+    /// Makes number literals arbitrary-precision ints and exact decimals
+    /// with `NumberMode::Exact`; only a quotient like `1 / 3` that needs more
+    /// than `DIVISION_DIGITS` digits is rounded. Floats are the default.
+    pub fn set_number_mode(&self, numbers: NumberMode) {
+        self.interpreter.set_number_mode(numbers);
+    }
+
+    /// Picks the engine that runs code from now on. Globals defined so far
+    /// stay visible to either one.
+    pub fn set_backend(&self, backend: Backend) {
+        self.interpreter.set_backend(backend);
+    }
+
+    /// After a collection, waits for the heap to grow to `factor` times
+    /// what survived before collecting again. Defaults to 2.
+    pub fn set_gc_growth(&self, factor: usize) {
+        self.interpreter.set_gc_growth(factor);
+    }
+
+    /// Collects garbage on every allocation, to flush out objects the
+    /// collector misses. Slow; meant for tests.
+    pub fn set_gc_stress(&self, stress: bool) {
+        self.interpreter.set_gc_stress(stress);
+    }
+
+    /// Frees every object that is only kept alive by a reference cycle and
+    /// returns how many there were. Collection also happens on its own as
+    /// the heap grows.
+    pub fn collect_garbage(&self) -> usize {
+        self.interpreter.collect_garbage()
+    }
+
+    pub fn eval_expression(&self, source: &str) -> Result<Value, Error> {
+        self.interpreter.eval_expression(source)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.get_global(name)
+    }
+
+    pub fn set_global(&self, name: &str, value: impl Into<Value>) {
+        self.interpreter.set_global(name, value);
+    }
 
-        for tok in &tokens {
-            println!("{}", tok.to_string());
+    /// Runs `source`, reporting any errors rather than returning them.
+    pub fn run(&self, source: String) {
+        if let Some(statements) = self.parse(source) {
+            match self.interpreter.interpret(statements) {
+                Ok(()) => {}
+                Err(error @ Error::CompileTimeError { .. }) => self.compile_error(&error),
+                Err(error) => self.runtime_error(&error),
+            }
         }
+    }
 
-        let parser = Parser::new(tokens);
-        let expr = parser.parse()?;
+    /// Compiles `source` to bytecode without running it and lists the
+    /// result, or reports why it doesn't compile and returns `None`.
+    pub fn disassemble(&self, source: String) -> Option<String> {
+        let statements = self.parse(source)?;
+        match compiler::Compiler::compile(&statements) {
+            Ok(script) => Some(script.disassemble()),
+            Err(error) => {
+                self.compile_error(&error);
+                None
+            }
+        }
+    }
 
-        Interpreter::new()
-            .interpret(expr)
-            .unwrap_or_else(|_err| String::from("Error"));
+    /// Compiles `source` to the bytes of a `.loxc` file, or reports why it
+    /// doesn't compile and returns `None`.
+    pub fn compile(&self, source: String) -> Option<Vec<u8>> {
+        let statements = self.parse(source)?;
+        match compiler::Compiler::compile(&statements) {
+            Ok(script) => Some(serializer::serialize(&script)),
+            Err(error) => {
+                self.compile_error(&error);
+                None
+            }
+        }
+    }
 
-        Ok(())
+    /// Runs a script compiled by `compile`. The bytes are checked first and
+    /// rejected with a compile error if they aren't a valid script for this
+    /// version.
+    pub fn run_compiled(&self, bytes: &[u8]) {
+        self.source.replace(String::new());
+        match serializer::deserialize(bytes) {
+            Ok(script) => {
+                if let Err(error) = self.interpreter.run_compiled(script) {
+                    self.runtime_error(&error);
+                }
+            }
+            Err(error) => self.compile_error(&error),
+        }
     }
 
-    pub fn run_file(&self, file_path: &String) -> io::Result<()> {
-        let mut file = File::open(file_path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
+    // scans, parses and resolves, reporting any errors
+    fn parse(&self, source: String) -> Option<Vec<Stmt>> {
+        self.source.replace(source.clone());
+        let mut scanner = Scanner::with_numbers(source, self.interpreter.number_mode());
+        let (tokens, mut errors) = scanner.scan_tokens();
+        let (statements, parse_errors) = Parser::new(tokens).parse();
+        errors.extend(parse_errors);
+        // in source order, with a lexical error ahead of what it confused
+        errors.sort_by_key(|error| error.span().map(|span| span.start));
+        if !errors.is_empty() {
+            for error in &errors {
+                self.compile_error(error);
+            }
+            return None;
+        }
+        let errors = Resolver::new().resolve(&statements);
+        if !errors.is_empty() {
+            for error in &errors {
+                self.compile_error(error);
+            }
+            return None;
+        }
+        Some(statements)
+    }
 
-        self.run(contents)
-            .throw(|err| format!("Err: {}", err.to_string()));
+    /// Runs a script, or a compiled one if its name ends in `.loxc`.
+    pub fn run_file(&self, file_path: &String) -> io::Result<()> {
+        if file_path.ends_with(".loxc") {
+            let bytes = fs::read(file_path)?;
+            self.file.replace(Some(file_path.clone()));
+            self.run_compiled(&bytes);
+        } else {
+            let contents = self.read_file(file_path)?;
+            self.run(contents);
+        }
 
         if *self.had_error.borrow() {
             process::exit(65);
@@ -78,37 +239,71 @@ impl Runner {
         Ok(())
     }
 
+    /// Prints the bytecode `file_path` compiles to instead of running it.
+    pub fn dump_file(&self, file_path: &String) -> io::Result<()> {
+        let contents = self.read_file(file_path)?;
+        match self.disassemble(contents) {
+            Some(listing) => print!("{}", listing),
+            None => process::exit(65),
+        }
+        Ok(())
+    }
+
+    /// Compiles `file_path` and writes the result to `output`.
+    pub fn compile_file(&self, file_path: &String, output: &str) -> io::Result<()> {
+        let contents = self.read_file(file_path)?;
+        match self.compile(contents) {
+            Some(bytes) => fs::write(output, bytes),
+            None => process::exit(65),
+        }
+    }
+
+    fn read_file(&self, file_path: &String) -> io::Result<String> {
+        let mut file = File::open(file_path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        self.file.replace(Some(file_path.clone()));
+        Ok(contents)
+    }
+
     pub fn run_prompt(&self) -> io::Result<()> {
         loop {
             print!("|> ");
             io::stdout().flush().unwrap();
 
             let mut line = String::new();
-            io::stdin().read_line(&mut line)?;
-            self.run(line)
-                .throw(|err| format!("Err: {}", err.to_string()));
+            if io::stdin().read_line(&mut line)? == 0 {
+                // end of input
+                return Ok(());
+            }
+            self.run(line);
             *self.had_error.borrow_mut() = false;
         }
     }
 
-    pub fn error(&self, token_local: &token::Token, message: &str) {
-        match token_local.token_type {
-            token::TokenType::EOF => self.report(token_local.line, " at end ", message),
-            _ => self.report(
-                token_local.line,
-                format!(" at '{}'", token_local.lexeme.as_str()).as_str(),
-                message,
-            ),
-        };
+    pub fn compile_error(&self, error: &Error) {
+        self.report(&Diagnostic::from(error));
     }
 
+    /// Reports a runtime error along with its call stack, innermost call first.
     pub fn runtime_error(&self, error: &Error) {
         *self.had_runtime_error.borrow_mut() = true;
-        eprintln!("{}", error.to_string());
+        self.emit(&Diagnostic::from(error));
     }
 
-    fn report(&self, line: usize, which: &str, message: &str) {
-        eprintln!("[line {}] Error {} : {}", line, which, message);
+    fn report(&self, diagnostic: &Diagnostic) {
+        self.emit(diagnostic);
         *self.had_error.borrow_mut() = true;
     }
+
+    fn emit(&self, diagnostic: &Diagnostic) {
+        let file = self.file.borrow();
+        match self.error_format {
+            ErrorFormat::Human => eprint!(
+                "{}",
+                diagnostic.render(&self.source.borrow(), file.as_deref(), self.color)
+            ),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(file.as_deref())),
+        }
+    }
 }
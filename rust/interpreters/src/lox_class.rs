@@ -0,0 +1,163 @@
+use crate::error::{Error, ErrorCode};
+use crate::gc::{address, trace_value, Address, Trace};
+use crate::interner::{intern, Symbol};
+use crate::interpreter::Interpreter;
+use crate::lox_function::LoxMethod;
+use crate::token::Token;
+use crate::typer::Typer;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+pub struct LoxClass {
+    pub name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<Symbol, Rc<dyn LoxMethod>>,
+    // interned once so that calls only compare handles
+    init: Symbol,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<Symbol, Rc<dyn LoxMethod>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+            init: intern("init"),
+        }
+    }
+
+    pub fn find_method(&self, name: &Symbol) -> Option<&Rc<dyn LoxMethod>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method),
+            None => match &self.superclass {
+                Some(superclass) => superclass.find_method(name),
+                None => None,
+            },
+        }
+    }
+
+    /// The `init` method, defined here or inherited, that calls run.
+    pub fn initializer(&self) -> Option<&Rc<dyn LoxMethod>> {
+        self.find_method(&self.init)
+    }
+
+    pub fn arity(&self) -> usize {
+        match self.initializer() {
+            Some(initializer) => initializer.arity(),
+            None => 0,
+        }
+    }
+
+    /// Calling a class creates a new instance and runs `init` on it, if any.
+    pub fn instantiate(
+        class: &Rc<LoxClass>,
+        interpreter: &Interpreter,
+        arguments: Vec<Typer>,
+    ) -> Result<Typer, Error> {
+        let instance = interpreter.alloc(RefCell::new(LoxInstance::new(Rc::clone(class))));
+        if let Some(initializer) = class.initializer() {
+            initializer
+                .bind(interpreter, Rc::clone(&instance))
+                .call(interpreter, arguments)?;
+        }
+        Ok(Typer::Instance(instance))
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        if let Some(superclass) = &self.superclass {
+            visit(address(superclass));
+        }
+        self.methods
+            .values()
+            .for_each(|method| visit(address(method)));
+        true
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<Symbol, Typer>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn class_name(&self) -> &str {
+        &self.class.name
+    }
+
+    /// Fields shadow methods; methods come back bound to `instance`.
+    pub fn get(
+        instance: &Rc<RefCell<LoxInstance>>,
+        name: &Token,
+        interpreter: &Interpreter,
+    ) -> Result<Typer, Error> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(&name.lexeme).cloned();
+        match method {
+            Some(method) => Ok(Typer::Callable(
+                method.bind(interpreter, Rc::clone(instance)),
+            )),
+            None => Err(Error::RuntimeError {
+                token: Some(name.clone()),
+                code: ErrorCode::UndefinedProperty,
+                message: format!("Undefined property '{}'.", name.lexeme),
+                trace: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: Typer) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+impl Trace for RefCell<LoxInstance> {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        match self.try_borrow() {
+            Ok(instance) => {
+                visit(address(&instance.class));
+                instance
+                    .fields
+                    .values()
+                    .for_each(|value| trace_value(value, visit));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut instance) = self.try_borrow_mut() {
+            instance.fields.clear();
+        }
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::environment::Environment;
+use crate::error::Error;
+use crate::gc::{address, Address, Trace};
+use crate::interner::intern;
+use crate::interpreter::Interpreter;
+use crate::lox_class::LoxInstance;
+use crate::stmt::Stmt;
+use crate::token::{Token, TokenType};
+use crate::typer::Typer;
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+pub trait LoxCallable {
+    fn arity(&self) -> usize;
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<Typer>) -> Result<Typer, Error>;
+    fn name(&self) -> &str;
+    /// Lets a backend recognise its own functions, which it can run without
+    /// going through `call`.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A function declared in a class body. Each backend has its own kind, so
+/// classes only rely on being able to bind one to an instance.
+pub trait LoxMethod: LoxCallable {
+    /// Returns the method with `this` bound to `instance`.
+    fn bind(
+        &self,
+        interpreter: &Interpreter,
+        instance: Rc<RefCell<LoxInstance>>,
+    ) -> Rc<dyn LoxCallable>;
+}
+
+impl fmt::Debug for dyn LoxCallable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FunctionKind {
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone)]
+pub struct LoxFunction {
+    name: Token,
+    params: Rc<Vec<Token>>,
+    body: Rc<Vec<Stmt>>,
+    closure: Rc<RefCell<Environment>>,
+    kind: FunctionKind,
+}
+
+impl LoxFunction {
+    pub fn new(
+        name: Token,
+        params: Rc<Vec<Token>>,
+        body: Rc<Vec<Stmt>>,
+        closure: Rc<RefCell<Environment>>,
+        kind: FunctionKind,
+    ) -> Self {
+        Self {
+            name,
+            params,
+            body,
+            closure,
+            kind,
+        }
+    }
+
+    fn this(&self) -> Result<Typer, Error> {
+        let this = Token::new(TokenType::THIS, "this", None, self.name.line);
+        self.closure.borrow().get(&this)
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<Typer>) -> Result<Typer, Error> {
+        let mut environment = Environment::new(Rc::clone(&self.closure));
+        for (param, argument) in self.params.iter().zip(arguments) {
+            environment.define(param.lexeme.clone(), Some(argument));
+        }
+
+        match interpreter.execute_block(&self.body, environment) {
+            Ok(()) | Err(Error::Return { .. }) if self.kind == FunctionKind::Initializer => {
+                self.this()
+            }
+            Ok(()) => Ok(Typer::Nil),
+            Err(Error::Return { value }) => Ok(value),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name.lexeme
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl LoxMethod for LoxFunction {
+    /// Copies the method with a closure that has `this` bound to `instance`.
+    fn bind(
+        &self,
+        interpreter: &Interpreter,
+        instance: Rc<RefCell<LoxInstance>>,
+    ) -> Rc<dyn LoxCallable> {
+        let mut environment = Environment::new(Rc::clone(&self.closure));
+        environment.define(intern("this"), Some(Typer::Instance(instance)));
+        interpreter.alloc(Self {
+            closure: interpreter.alloc(RefCell::new(environment)),
+            ..self.clone()
+        })
+    }
+}
+
+impl Trace for LoxFunction {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        visit(address(&self.closure));
+        true
+    }
+}
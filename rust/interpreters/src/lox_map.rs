@@ -0,0 +1,206 @@
+use crate::gc::{trace_value, Address, Trace};
+use crate::interner::Symbol;
+use crate::typer::Typer;
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, ToPrimitive};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The values that can key a map. Whole floats become `Int` keys, so
+/// `m[1]` and `m[1.0]` are the same entry, as `1 == 1.0` would suggest;
+/// other floats are stored by their bits. Exact numbers key the same way
+/// when they fit, and otherwise by value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Str(Symbol),
+    Int(i64),
+    Number(u64),
+    BigInt(BigInt),
+    Decimal(BigDecimal),
+    Boolean(bool),
+}
+
+impl MapKey {
+    /// `None` for values that can't be keys: everything but strings,
+    /// booleans and numbers other than NaN.
+    pub fn from_typer(value: &Typer) -> Option<Self> {
+        match value {
+            Typer::Str(st) => Some(MapKey::Str(st.clone())),
+            Typer::Boolean(b) => Some(MapKey::Boolean(*b)),
+            Typer::Int(num) => Some(MapKey::Int(*num)),
+            Typer::BigInt(num) => Some(
+                num.to_i64()
+                    .map_or_else(|| MapKey::BigInt((**num).clone()), MapKey::Int),
+            ),
+            Typer::Decimal(num) if num.is_integer() => {
+                let (num, _) = num.with_scale(0).into_bigint_and_exponent();
+                MapKey::from_typer(&Typer::from(num))
+            }
+            Typer::Decimal(num) => Some(MapKey::Decimal(num.normalized())),
+            Typer::Number(num) if num.is_nan() => None,
+            Typer::Number(num)
+                if num.fract() == 0.0 && *num >= i64::MIN as f64 && *num < i64::MAX as f64 =>
+            {
+                Some(MapKey::Int(*num as i64))
+            }
+            Typer::Number(num) => Some(MapKey::Number(num.to_bits())),
+            _ => None,
+        }
+    }
+
+    pub fn to_typer(&self) -> Typer {
+        match self {
+            MapKey::Str(st) => Typer::Str(st.clone()),
+            MapKey::Int(num) => Typer::Int(*num),
+            MapKey::BigInt(num) => Typer::from(num.clone()),
+            MapKey::Decimal(num) => Typer::from(num.clone()),
+            MapKey::Number(bits) => Typer::Number(f64::from_bits(*bits)),
+            MapKey::Boolean(b) => Typer::Boolean(*b),
+        }
+    }
+}
+
+/// A map that remembers insertion order, so printing and `keys`/`values`
+/// come out the same on every run.
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, Typer)>,
+    positions: HashMap<MapKey, usize>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&Typer> {
+        self.positions.get(key).map(|&at| &self.entries[at].1)
+    }
+
+    pub fn contains(&self, key: &MapKey) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    /// Overwriting a key keeps its original position.
+    pub fn insert(&mut self, key: MapKey, value: Typer) {
+        match self.positions.get(&key) {
+            Some(&at) => self.entries[at].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<Typer> {
+        let at = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(at);
+        for (key, _) in &self.entries[at..] {
+            if let Some(position) = self.positions.get_mut(key) {
+                *position -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&MapKey, &Typer)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl Trace for RefCell<LoxMap> {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        match self.try_borrow() {
+            Ok(map) => {
+                map.entries
+                    .iter()
+                    .for_each(|(_, value)| trace_value(value, visit));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut map) = self.try_borrow_mut() {
+            *map = LoxMap::new();
+        }
+    }
+}
+
+impl fmt::Display for LoxMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, value)) in self.entries().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", key.to_typer(), value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn keeps_insertion_order() {
+        let key = |value: Typer| MapKey::from_typer(&value).unwrap();
+        let mut map = LoxMap::new();
+        map.insert(key(Typer::from("b")), Typer::from(1.0));
+        map.insert(key(Typer::from(2.0)), Typer::from(2.0));
+        map.insert(key(Typer::from(true)), Typer::from(3.0));
+        map.insert(key(Typer::from("b")), Typer::from(4.0));
+        assert_eq!(map.to_string(), "{b: 4, 2: 2, true: 3}");
+
+        assert_eq!(map.remove(&key(Typer::from(2.0))).unwrap().to_string(), "2");
+        assert!(map.remove(&key(Typer::from(2.0))).is_none());
+        assert_eq!(map.get(&key(Typer::from(true))).unwrap().to_string(), "3");
+        assert_eq!(map.len(), 2);
+
+        assert_eq!(key(Typer::from(-0.0)), key(Typer::from(0.0)));
+        assert_eq!(key(Typer::from(1.0)), key(Typer::from(1)));
+        assert_ne!(key(Typer::from(1.5)), key(Typer::from(1)));
+        let decimal = |text: &str| Typer::from(text.parse::<BigDecimal>().unwrap());
+        assert_eq!(key(decimal("2.00")), key(Typer::from(2)));
+        assert_eq!(key(decimal("0.50")), key(decimal("0.5")));
+        assert_eq!(key(Typer::from(BigInt::from(7))), key(Typer::from(7)));
+        assert!(MapKey::from_typer(&Typer::Number(f64::NAN)).is_none());
+        assert!(MapKey::from_typer(&Typer::Nil).is_none());
+    }
+
+    #[test]
+    fn maps_that_contain_themselves_print() {
+        let key = |name: &str| MapKey::from_typer(&Typer::from(name)).unwrap();
+        let map = Rc::new(RefCell::new(LoxMap::new()));
+        map.borrow_mut()
+            .insert(key("me"), Typer::Map(Rc::clone(&map)));
+        assert_eq!(Typer::Map(Rc::clone(&map)).to_string(), "{me: {...}}");
+
+        // through a list and back
+        let list = Rc::new(RefCell::new(vec![Typer::Map(Rc::clone(&map))]));
+        map.borrow_mut()
+            .insert(key("list"), Typer::List(Rc::clone(&list)));
+        assert_eq!(
+            Typer::Map(Rc::clone(&map)).to_string(),
+            "{me: {...}, list: [{...}]}"
+        );
+        assert_eq!(
+            Typer::List(Rc::clone(&list)).to_string(),
+            "[{me: {...}, list: [...]}]"
+        );
+        *map.borrow_mut() = LoxMap::new();
+    }
+}
@@ -1,18 +1,68 @@
 use std::io;
+use std::path::Path;
 use std::{env, process};
 
-use interpreters::Runner;
+use interpreters::diagnostics::ErrorFormat;
+use interpreters::{Backend, NumberMode, Runner};
+
+fn usage() -> ! {
+    println!("--Usage: eksc [--error-format=human|json] [--backend=tree-walk|bytecode] [--allow-io] [--exact-numbers] [--gc-stress] [--dump-bytecode] [script]--");
+    println!("--       eksc compile script.lox [-o script.loxc]--");
+    process::exit(64);
+}
+
+// script.lox compiles to script.loxc next to it
+fn default_output(script: &str) -> String {
+    Path::new(script)
+        .with_extension("loxc")
+        .to_string_lossy()
+        .into_owned()
+}
 
 fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let mut runner = Runner::new();
+    let mut scripts = Vec::new();
+    let mut dump_bytecode = false;
 
-    let runner = Runner::new();
+    for arg in env::args().skip(1) {
+        if let Some(format) = arg.strip_prefix("--error-format=") {
+            match ErrorFormat::from_name(format) {
+                Some(format) => runner.error_format = format,
+                None => usage(),
+            }
+        } else if let Some(backend) = arg.strip_prefix("--backend=") {
+            match Backend::from_name(backend) {
+                Some(backend) => runner.set_backend(backend),
+                None => usage(),
+            }
+        } else if arg == "--allow-io" {
+            runner.enable_io();
+        } else if arg == "--exact-numbers" {
+            runner.set_number_mode(NumberMode::Exact);
+        } else if arg == "--gc-stress" {
+            runner.set_gc_stress(true);
+        } else if arg == "--dump-bytecode" {
+            dump_bytecode = true;
+        } else if arg.starts_with("--") {
+            usage();
+        } else {
+            scripts.push(arg);
+        }
+    }
 
-    if args.len() > 2 {
-        println!("--Usage: eksc [script]--");
-        process::exit(64);
-    } else if args.len() == 2 {
-        runner.run_file(&args[1])?;
+    if scripts.first().map(String::as_str) == Some("compile") {
+        let (script, output) = match &scripts[1..] {
+            [script] => (script, default_output(script)),
+            [script, flag, output] if flag == "-o" => (script, output.clone()),
+            _ => usage(),
+        };
+        runner.compile_file(script, &output)?;
+    } else if scripts.len() > 1 || (dump_bytecode && scripts.is_empty()) {
+        usage();
+    } else if dump_bytecode {
+        runner.dump_file(&scripts[0])?;
+    } else if let Some(script) = scripts.first() {
+        runner.run_file(script)?;
     } else {
         runner.run_prompt().ok();
         println!("Is Error: {}", *runner.had_error.borrow());
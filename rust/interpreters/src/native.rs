@@ -0,0 +1,104 @@
+use crate::error::Error;
+use crate::interpreter::Interpreter;
+use crate::lox_function::LoxCallable;
+use crate::typer::Typer;
+use std::any::Any;
+
+pub type NativeFn = dyn Fn(&Interpreter, Vec<Typer>) -> Result<Typer, Error>;
+
+/// A function implemented in Rust and callable from scripts like any other.
+/// The interpreter checks the arity before `function` runs, so it always
+/// receives exactly `arity` arguments.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&Interpreter, Vec<Typer>) -> Result<Typer, Error> + 'static,
+    ) -> Self {
+        Self {
+            name: String::from(name),
+            arity,
+            function: Box::new(function),
+        }
+    }
+}
+
+impl LoxCallable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<Typer>) -> Result<Typer, Error> {
+        let value = (self.function)(interpreter, arguments)?;
+        interpreter.track_value(&value);
+        Ok(value)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, ErrorCode};
+    use crate::test_support::{global, interpreter_on, on_each_backend, parse, run};
+    use crate::typer::{FromTyper, Typer};
+
+    on_each_backend!(scripts_call_registered_functions, |backend| {
+        let interpreter = interpreter_on(backend);
+        interpreter.define_native("hypot", 2, |_, arguments| {
+            let mut arguments = arguments.into_iter();
+            let a = f64::from_typer(arguments.next().unwrap())?;
+            let b = f64::from_typer(arguments.next().unwrap())?;
+            Ok(Typer::from((a * a + b * b).sqrt()))
+        });
+        interpreter.define_native("greeting", 1, |_, arguments| {
+            let name = Option::<String>::from_typer(arguments[0].clone())?;
+            Ok(Typer::from(format!("hi {}", name.unwrap_or_default())))
+        });
+
+        run(
+            &interpreter,
+            "var h = hypot(3, 4); var g = greeting(nil); var f = hypot;",
+        );
+        assert_eq!(global(&interpreter, "h"), "5");
+        assert_eq!(global(&interpreter, "g"), "hi ");
+        assert_eq!(global(&interpreter, "f"), "<fn hypot>");
+    });
+
+    on_each_backend!(native_errors_point_at_the_call, |backend| {
+        let interpreter = interpreter_on(backend);
+        interpreter.define_native("half", 1, |_, arguments| {
+            Ok(Typer::from(f64::from_typer(arguments[0].clone())? / 2.0))
+        });
+
+        match interpreter.interpret(parse("half(\"x\");")) {
+            Err(Error::RuntimeError {
+                token: Some(token),
+                code,
+                message,
+                ..
+            }) => {
+                assert_eq!(token.lexeme, ")");
+                assert_eq!(code, ErrorCode::TypeMismatch);
+                assert_eq!(message, "Expected number but got string.");
+            }
+            _ => panic!("expected a type mismatch"),
+        }
+        match interpreter.interpret(parse("half(1, 2);")) {
+            Err(Error::RuntimeError { code, .. }) => assert_eq!(code, ErrorCode::ArityMismatch),
+            _ => panic!("expected an arity error"),
+        }
+    });
+}
@@ -1,5 +1,5 @@
-use crate::error::Error;
-use crate::expr::Expr;
+use crate::error::{Error, ErrorCode};
+use crate::expr::{Depth, Expr};
 use crate::stmt::Stmt;
 use crate::token::{
     Literal::*,
@@ -7,10 +7,12 @@ use crate::token::{
     TokenType::{self, *},
 };
 use std::cell::RefCell;
+use std::rc::Rc;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: RefCell<usize>,
+    diagnostics: RefCell<Vec<Error>>,
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -20,33 +22,112 @@ impl Parser {
         Self {
             tokens,
             current: RefCell::new(0),
+            diagnostics: RefCell::new(Vec::new()),
         }
     }
 
-    pub fn parse(&self) -> Result<Vec<Stmt>> {
+    /// Parses the whole token stream, recovering at statement boundaries so
+    /// that every syntax error is reported. Declarations that failed to
+    /// parse are left out of the returned statements.
+    pub fn parse(&self) -> (Vec<Stmt>, Vec<Error>) {
         let mut declarations: Vec<Stmt> = Vec::new();
 
         while !self.is_at_end() {
-            let declaration = match self.declaration() {
-                Ok(stmt) => declarations.push(stmt),
-                Err(err) => return Err(err),
-            };
+            if let Some(declaration) = self.declaration() {
+                declarations.push(declaration);
+            }
+        }
+        (declarations, self.diagnostics.replace(Vec::new()))
+    }
+
+    /// Parses the tokens as a single expression, as hosts evaluating a
+    /// snippet need. Returns the first error instead of recovering.
+    pub fn parse_expression(&self) -> Result<Expr, Error> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            return Err(self.error(
+                self.peek().clone(),
+                ErrorCode::ExpectedToken,
+                "Expect end of expression",
+            ));
+        }
+        match self.diagnostics.replace(Vec::new()).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(expr),
         }
-        Ok(declarations)
     }
 
-    fn declaration(&self) -> Result<Stmt, Error> {
-        if self.matching(&[VAR]) {
-            return match self.var_declaration() {
-                Ok(decl) => Ok(decl),
-                Err(err) => {
-                    self.synchronize();
-                    return Err(err);
+    fn declaration(&self) -> Option<Stmt> {
+        let declaration = if self.matching(&[CLASS]) {
+            self.class_declaration()
+        } else if self.matching(&[FUN]) {
+            self.function("function")
+        } else if self.matching(&[VAR]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        };
+
+        match declaration {
+            Ok(declaration) => Some(declaration),
+            Err(err) => {
+                self.diagnostics.borrow_mut().push(err);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn class_declaration(&self) -> Result<Stmt, Error> {
+        let name = self.consume(&IDENTIFIER, "Expect class name")?;
+
+        let mut superclass = None;
+        if self.matching(&[LESS]) {
+            let superclass_name = self.consume(&IDENTIFIER, "Expect superclass name")?;
+            superclass = Some(Expr::Variable(superclass_name, Depth::default()));
+        }
+
+        self.consume(&LEFT_BRACE, "Expect '{' before class body")?;
+        let mut methods = Vec::new();
+        while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+        self.consume(&RIGHT_BRACE, "Expect '}' after class body")?;
+
+        Ok(Stmt::Class(Box::new(name), superclass, methods))
+    }
+
+    fn function(&self, kind: &str) -> Result<Stmt, Error> {
+        let name = self.consume(&IDENTIFIER, &format!("Expect {} name", kind))?;
+        self.consume(&LEFT_PAREN, &format!("Expect '(' after {} name", kind))?;
+
+        let mut params = Vec::new();
+        if !self.check(&RIGHT_PAREN) {
+            loop {
+                if params.len() >= 255 {
+                    // reported without unwinding: the parser isn't confused
+                    self.report(self.error(
+                        self.peek().clone(),
+                        ErrorCode::TooManyArguments,
+                        "Can't have more than 255 parameters",
+                    ));
                 }
-            };
+                params.push(self.consume(&IDENTIFIER, "Expect parameter name")?);
+                if !self.matching(&[COMMA]) {
+                    break;
+                }
+            }
         }
+        self.consume(&RIGHT_PAREN, "Expect ')' after parameters")?;
+
+        self.consume(&LEFT_BRACE, &format!("Expect '{{' before {} body", kind))?;
+        let body = self.block()?;
 
-        self.statement()
+        Ok(Stmt::Function(
+            Box::new(name),
+            Rc::new(params),
+            Rc::new(body),
+        ))
     }
 
     fn var_declaration(&self) -> Result<Stmt, Error> {
@@ -56,38 +137,142 @@ impl Parser {
         if self.matching(&[EQUAL]) {
             initializer = Some(self.expression()?);
         }
+        self.consume(&SEMICOLON, "Expect ';' after variable declaration")?;
 
-        Ok(Stmt::Var(Box::new((name).unwrap()), initializer))
+        Ok(Stmt::Var(Box::new(name), initializer))
     }
 
     fn statement(&self) -> Result<Stmt, Error> {
+        if self.matching(&[FOR]) {
+            return self.for_statement();
+        }
+        if self.matching(&[IF]) {
+            return self.if_statement();
+        }
         if self.matching(&[PRINT]) {
             return self.print_statement();
         }
+        if self.matching(&[RETURN]) {
+            return self.return_statement();
+        }
+        if self.matching(&[WHILE]) {
+            return self.while_statement();
+        }
         if self.matching(&[LEFT_BRACE]) {
-            return Ok(Stmt::Block(Box::new(self.block()?)));
+            let open = self.previous().span;
+            let statements = self.block()?;
+            return Ok(Stmt::Block(statements, open.to(self.previous().span)));
         }
         self.expression_statement()
     }
 
+    /// `for` has no node of its own: it is desugared into a `while` loop
+    /// wrapped in a block that holds the initializer.
+    fn for_statement(&self) -> Result<Stmt, Error> {
+        let keyword = self.previous().span;
+        self.consume(&LEFT_PAREN, "Expect '(' after 'for'")?;
+
+        let initializer = if self.matching(&[SEMICOLON]) {
+            None
+        } else if self.matching(&[VAR]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if !self.check(&SEMICOLON) {
+            self.expression()?
+        } else {
+            Expr::LiteralExpr(Some(Bool(true)), keyword)
+        };
+        self.consume(&SEMICOLON, "Expect ';' after loop condition")?;
+
+        let increment = if !self.check(&RIGHT_PAREN) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(&RIGHT_PAREN, "Expect ')' after for clauses")?;
+
+        let mut body = self.statement()?;
+
+        if let Some(increment) = increment {
+            let span = body.span().to(increment.span());
+            body = Stmt::Block(vec![body, Stmt::Expression(Box::new(increment))], span);
+        }
+        body = Stmt::While(Box::new(condition), Box::new(body), keyword);
+        if let Some(initializer) = initializer {
+            let span = keyword.to(body.span());
+            body = Stmt::Block(vec![initializer, body], span);
+        }
+
+        Ok(body)
+    }
+
+    fn if_statement(&self) -> Result<Stmt, Error> {
+        let keyword = self.previous().span;
+        self.consume(&LEFT_PAREN, "Expect '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(&RIGHT_PAREN, "Expect ')' after if condition")?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if self.matching(&[ELSE]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If(
+            Box::new(condition),
+            Box::new(then_branch),
+            else_branch,
+            keyword,
+        ))
+    }
+
+    fn return_statement(&self) -> Result<Stmt, Error> {
+        let keyword = self.previous().clone();
+        let value = if !self.check(&SEMICOLON) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(&SEMICOLON, "Expect ';' after return value")?;
+
+        Ok(Stmt::Return(Box::new(keyword), value))
+    }
+
+    fn while_statement(&self) -> Result<Stmt, Error> {
+        let keyword = self.previous().span;
+        self.consume(&LEFT_PAREN, "Expect '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(&RIGHT_PAREN, "Expect ')' after condition")?;
+        let body = self.statement()?;
+
+        Ok(Stmt::While(Box::new(condition), Box::new(body), keyword))
+    }
+
     fn print_statement(&self) -> Result<Stmt, Error> {
+        let keyword = self.previous().span;
         let value = self.expression()?;
-        self.consume(&SEMICOLON, "Expect ';' after expression");
-        Ok(Stmt::Print(Box::new(value)))
+        self.consume(&SEMICOLON, "Expect ';' after expression")?;
+        Ok(Stmt::Print(Box::new(value), keyword))
     }
 
     fn expression_statement(&self) -> Result<Stmt, Error> {
         let expr = self.expression()?;
-        self.consume(&SEMICOLON, "Expect ';' after expression");
+        self.consume(&SEMICOLON, "Expect ';' after expression")?;
         Ok(Stmt::Expression(Box::new(expr)))
     }
 
     fn block(&self) -> Result<Vec<Stmt>, Error> {
-        let statements = Vec::new();
+        let mut statements = Vec::new();
         while !self.check(&RIGHT_BRACE) && !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(declaration) = self.declaration() {
+                statements.push(declaration);
+            }
         }
-        self.consume(&RIGHT_BRACE, "Expect '}' after block");
+        self.consume(&RIGHT_BRACE, "Expect '}' after block")?;
         Ok(statements)
     }
 
@@ -97,25 +282,45 @@ impl Parser {
 
     fn assignment(&self) -> Result<Expr, Error> {
         let expr = self.comma()?;
+        self.assign_to(expr, Self::assignment)
+    }
 
+    /// An assignment whose operands stop at commas, for places where commas
+    /// separate items: call arguments and list and map literals.
+    fn argument(&self) -> Result<Expr, Error> {
+        let expr = self.ternary()?;
+        self.assign_to(expr, Self::argument)
+    }
+
+    // makes `target` an assignment of what `value` parses if `=` follows
+    fn assign_to(
+        &self,
+        target: Expr,
+        value: fn(&Self) -> Result<Expr, Error>,
+    ) -> Result<Expr, Error> {
         if self.matching(&[EQUAL]) {
             let equals = self.previous();
-            let value = self.assignment()?;
-
-            return match expr {
-                Expr::Variable(var) => {
-                    let name = var;
-                    let res = Ok(Expr::Assign {
-                        name,
-                        value: Box::new(value),
-                    });
-                    res
+            let value = value(self)?;
+
+            return match target {
+                Expr::Variable(name, _depth) => Ok(Expr::Assign {
+                    name,
+                    value: Box::new(value),
+                    depth: Depth::default(),
+                }),
+                Expr::Get(object, name) => Ok(Expr::Set(object, name, Box::new(value))),
+                Expr::Index(object, bracket, index) => {
+                    Ok(Expr::IndexSet(object, bracket, index, Box::new(value)))
                 }
-                _ => Err(self.error(equals.clone(), "Invalid assignment target")),
+                _ => Err(self.error(
+                    equals.clone(),
+                    ErrorCode::InvalidAssignmentTarget,
+                    "Invalid assignment target",
+                )),
             };
         }
 
-        Ok(expr)
+        Ok(target)
     }
 
     fn comma(&self) -> Result<Expr, Error> {
@@ -131,7 +336,7 @@ impl Parser {
     }
 
     fn ternary(&self) -> Result<Expr, Error> {
-        let mut expr = self.equality()?;
+        let mut expr = self.or()?;
 
         if self.matching(&[QUESTION]) {
             let first = self.ternary()?;
@@ -149,6 +354,30 @@ impl Parser {
         Ok(expr)
     }
 
+    fn or(&self) -> Result<Expr, Error> {
+        let mut expr = self.and()?;
+
+        while self.matching(&[OR]) {
+            let operator = self.previous();
+            let right = self.and()?;
+            expr = Expr::Logical(Box::new(expr), operator.clone(), Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&self) -> Result<Expr, Error> {
+        let mut expr = self.equality()?;
+
+        while self.matching(&[AND]) {
+            let operator = self.previous();
+            let right = self.equality()?;
+            expr = Expr::Logical(Box::new(expr), operator.clone(), Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
     fn equality(&self) -> Result<Expr, Error> {
         let mut expr = self.comparison()?;
 
@@ -188,7 +417,7 @@ impl Parser {
     fn multiplication(&self) -> Result<Expr, Error> {
         let mut expr = self.unary()?;
 
-        while self.matching(&[SLASH, STAR]) {
+        while self.matching(&[SLASH, STAR, TILDE_SLASH, PERCENT]) {
             let operator = self.previous();
             let right = self.unary()?;
             expr = Expr::Binary(Box::new(expr), operator.clone(), Box::new(right));
@@ -199,47 +428,144 @@ impl Parser {
     fn unary(&self) -> Result<Expr, Error> {
         if self.matching(&[BANG, MINUS]) {
             let operator = self.previous();
-            let right = self.expression()?;
+            let right = self.unary()?;
             return Ok(Expr::Unary(operator.clone(), Box::new(right)));
         }
-        self.primary()
+        self.call()
+    }
+
+    fn call(&self) -> Result<Expr, Error> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.matching(&[LEFT_PAREN]) {
+                expr = self.finish_call(expr)?;
+            } else if self.matching(&[DOT]) {
+                let name = self.consume(&IDENTIFIER, "Expect property name after '.'")?;
+                expr = Expr::Get(Box::new(expr), name);
+            } else if self.matching(&[LEFT_BRACKET]) {
+                let index = self.expression()?;
+                let bracket = self.consume(&RIGHT_BRACKET, "Expect ']' after index")?;
+                expr = Expr::Index(Box::new(expr), bracket, Box::new(index));
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    /// Arguments are parsed with `argument` so the comma operator doesn't
+    /// swallow the separators between them.
+    fn finish_call(&self, callee: Expr) -> Result<Expr, Error> {
+        let mut arguments = Vec::new();
+        if !self.check(&RIGHT_PAREN) {
+            loop {
+                if arguments.len() >= 255 {
+                    self.report(self.error(
+                        self.peek().clone(),
+                        ErrorCode::TooManyArguments,
+                        "Can't have more than 255 arguments",
+                    ));
+                }
+                arguments.push(self.argument()?);
+                if !self.matching(&[COMMA]) {
+                    break;
+                }
+            }
+        }
+        let paren = self.consume(&RIGHT_PAREN, "Expect ')' after arguments")?;
+
+        Ok(Expr::Call(Box::new(callee), paren, arguments))
     }
 
     fn primary(&self) -> Result<Expr, Error> {
         if self.matching(&[FALSE]) {
-            return Ok(Expr::LiteralExpr(Some(Bool(false))));
+            return Ok(Expr::LiteralExpr(Some(Bool(false)), self.previous().span));
         }
         if self.matching(&[TRUE]) {
-            return Ok(Expr::LiteralExpr(Some(Bool(true))));
+            return Ok(Expr::LiteralExpr(Some(Bool(true)), self.previous().span));
         }
         if self.matching(&[NIL]) {
-            return Ok(Expr::LiteralExpr(Some(Nil)));
+            return Ok(Expr::LiteralExpr(Some(Nil), self.previous().span));
+        }
+        if self.matching(&[LEFT_BRACKET]) {
+            return self.list();
+        }
+        // only reachable in expression position: a statement starting with
+        // '{' is always a block
+        if self.matching(&[LEFT_BRACE]) {
+            return self.map();
+        }
+        if self.matching(&[SUPER]) {
+            let keyword = self.previous().clone();
+            self.consume(&DOT, "Expect '.' after 'super'")?;
+            let method = self.consume(&IDENTIFIER, "Expect superclass method name")?;
+            return Ok(Expr::Super(keyword, method, Depth::default()));
+        }
+        if self.matching(&[THIS]) {
+            return Ok(Expr::This(self.previous().clone(), Depth::default()));
+        }
+        if self.matching(&[IDENTIFIER]) {
+            return Ok(Expr::Variable(self.previous().clone(), Depth::default()));
         }
         if self.matching(&[NUMBER, STRING]) {
-            let lit = self.previous().literal;
-            return Ok(Expr::LiteralExpr(lit));
+            let lit = self.previous().literal.clone();
+            return Ok(Expr::LiteralExpr(lit, self.previous().span));
         }
         if self.matching(&[LEFT_PAREN]) {
-            let expr = self.expression().unwrap();
-            self.consume(&RIGHT_PAREN, "Expect ) after expression").ok();
-            return Ok(Expr::Grouping(Box::new(expr)));
+            let open = self.previous().span;
+            let expr = self.expression()?;
+            let close = self.consume(&RIGHT_PAREN, "Expect ) after expression")?;
+            return Ok(Expr::Grouping(Box::new(expr), open.to(close.span)));
         }
         if self.is_at_end() {
-            return Ok(Expr::LiteralExpr(Some(Nil)));
+            return Ok(Expr::LiteralExpr(Some(Nil), self.peek().span));
+        }
+
+        Err(self.error(
+            self.peek().clone(),
+            ErrorCode::ExpectedExpression,
+            "Unable to resolve token here",
+        ))
+    }
+
+    /// Elements are parsed like call arguments; a trailing comma is allowed.
+    fn list(&self) -> Result<Expr, Error> {
+        let open = self.previous().span;
+        let mut elements = Vec::new();
+        while !self.check(&RIGHT_BRACKET) {
+            elements.push(self.argument()?);
+            if !self.matching(&[COMMA]) {
+                break;
+            }
         }
+        let close = self.consume(&RIGHT_BRACKET, "Expect ']' after list elements")?;
+        Ok(Expr::List(elements, open.to(close.span)))
+    }
 
-        Err(self.error(self.peek().clone(), "Unable to resolve token here"))
+    fn map(&self) -> Result<Expr, Error> {
+        let open = self.previous().span;
+        let mut entries = Vec::new();
+        while !self.check(&RIGHT_BRACE) {
+            let key = self.argument()?;
+            let colon = self.consume(&COLON, "Expect ':' after map key")?;
+            entries.push((key, colon, self.argument()?));
+            if !self.matching(&[COMMA]) {
+                break;
+            }
+        }
+        let close = self.consume(&RIGHT_BRACE, "Expect '}' after map entries")?;
+        Ok(Expr::Map(entries, open.to(close.span)))
     }
 
-    #[allow(dead_code)]
-    fn synchronize(&mut self) {
+    fn synchronize(&self) {
         self.advance();
 
         while !self.is_at_end() {
-            match self.previous().token_type {
-                TokenType::SEMICOLON => return,
-                _ => {}
-            };
+            if self.previous().token_type == SEMICOLON {
+                return;
+            }
 
             match self.peek().token_type {
                 CLASS | FUN | VAR | FOR | IF | WHILE | PRINT | RETURN => {
@@ -252,18 +578,23 @@ impl Parser {
         }
     }
 
-    fn error(&self, token: Token, message: &str) -> Error {
+    fn report(&self, error: Error) {
+        self.diagnostics.borrow_mut().push(error);
+    }
+
+    fn error(&self, token: Token, code: ErrorCode, message: &str) -> Error {
         Error::CompileTimeError {
             token: Some(token),
+            code,
             message: String::from(message),
         }
     }
 
-    fn consume(&self, token_type: &TokenType, message: &str) -> Result<Option<Token>, Error> {
+    fn consume(&self, token_type: &TokenType, message: &str) -> Result<Token, Error> {
         if self.check(token_type) {
             return Ok(self.advance());
         }
-        Err(self.error(self.peek().clone(), message))
+        Err(self.error(self.peek().clone(), ErrorCode::ExpectedToken, message))
     }
 
     fn matching(&self, types: &[TokenType]) -> bool {
@@ -276,11 +607,11 @@ impl Parser {
         false
     }
 
-    fn advance(&self) -> Option<Token> {
+    fn advance(&self) -> Token {
         if !self.is_at_end() {
-            self.current.replace(*self.current.borrow() + 1);
+            *self.current.borrow_mut() += 1;
         }
-        None
+        self.previous().clone()
     }
 
     fn check(&self, token_type: &TokenType) -> bool {
@@ -292,17 +623,113 @@ impl Parser {
     }
 
     fn peek(&self) -> &Token {
-        &self.tokens.get(self.current.borrow().clone()).unwrap()
+        self.tokens.get(*self.current.borrow()).unwrap()
     }
 
     fn previous(&self) -> &Token {
-        self.tokens.get(self.current.borrow().clone() - 1).unwrap()
+        self.tokens.get(*self.current.borrow() - 1).unwrap()
     }
 
     fn is_at_end(&self) -> bool {
-        match self.peek().token_type {
-            EOF => true,
-            _ => false,
+        self.peek().token_type == EOF
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_printer::RPN;
+    use crate::scanner::Scanner;
+    use crate::test_support::scan;
+
+    fn parse(source: &str) -> (Vec<Stmt>, Vec<Error>) {
+        Parser::new(scan(source)).parse()
+    }
+
+    fn messages(errors: &[Error]) -> Vec<String> {
+        errors
+            .iter()
+            .map(|error| match error {
+                Error::CompileTimeError { message, .. } => message.clone(),
+                _ => panic!("expected only compile time errors"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let (statements, errors) = parse("var = 1;\nprint 2;\nvar b = ;\nprint (3;\nprint 4;");
+
+        assert_eq!(
+            messages(&errors),
+            vec![
+                "Expect variable name",
+                "Unable to resolve token here",
+                "Expect ) after expression",
+            ]
+        );
+        // the statements in between still make it into the partial AST
+        assert_eq!(statements.len(), 2);
+    }
+
+    #[test]
+    fn parses_past_lexical_errors() {
+        let (tokens, mut errors) =
+            Scanner::new(String::from("var a = @;\nvar = 1;\nprint (;")).scan_tokens();
+        errors.extend(Parser::new(tokens).parse().1);
+
+        assert_eq!(
+            messages(&errors),
+            vec![
+                "Unexpected character",
+                "Unable to resolve token here",
+                "Expect variable name",
+                "Unable to resolve token here",
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        let (statements, errors) = parse("{ var a = 1; a = ; print a; }\nprint 1;");
+
+        assert_eq!(messages(&errors), vec!["Unable to resolve token here"]);
+        assert_eq!(statements.len(), 2);
+        match &statements[0] {
+            Stmt::Block(inner, _) => assert_eq!(inner.len(), 2),
+            _ => panic!("expected a block"),
+        }
+    }
+
+    #[test]
+    fn comma_binds_tighter_than_assignment() {
+        let expression = |source: &str| match parse(source).0.pop() {
+            Some(Stmt::Expression(expr)) => *expr,
+            _ => panic!("expected an expression statement"),
+        };
+
+        match expression("x = 1, 2;") {
+            Expr::Assign { value, .. } => assert_eq!(value.print_rpn(), "1 2 ,"),
+            _ => panic!("expected the whole comma expression to be assigned"),
+        }
+        // commas between arguments and elements still separate them
+        match expression("f(a = 1, b);") {
+            Expr::Call(_, _, arguments) => {
+                assert!(matches!(arguments[0], Expr::Assign { .. }));
+                assert_eq!(arguments.len(), 2);
+            }
+            _ => panic!("expected a call"),
         }
+        match expression("[a = 1, 2];") {
+            Expr::List(elements, _) => assert_eq!(elements.len(), 2),
+            _ => panic!("expected a list"),
+        }
+    }
+
+    #[test]
+    fn clean_source_has_no_errors() {
+        let (statements, errors) = parse("var a = 1; if (a) print a; else print nil;");
+        assert!(errors.is_empty());
+        assert_eq!(statements.len(), 2);
     }
 }
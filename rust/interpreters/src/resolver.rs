@@ -0,0 +1,415 @@
+use crate::error::{Error, ErrorCode};
+use crate::expr::{Depth, Expr};
+use crate::interner::{intern, Symbol};
+use crate::lox_function::FunctionKind;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassKind {
+    Class,
+    Subclass,
+}
+
+/// Static pass run between parsing and interpreting. It stores on every
+/// variable expression how many scopes separate it from its binding and
+/// rejects programs that are well formed but meaningless.
+pub struct Resolver {
+    // `false` while a variable is declared but its initializer hasn't run yet
+    scopes: Vec<HashMap<Symbol, bool>>,
+    current_function: Option<FunctionKind>,
+    current_class: Option<ClassKind>,
+    diagnostics: Vec<Error>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            current_function: None,
+            current_class: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Resolves every statement, carrying on past errors so that all of
+    /// them are reported. The program may only be run if none are returned.
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Vec<Error> {
+        self.resolve_block(statements);
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Resolves a single expression, as hosts evaluating a snippet need.
+    /// Returns the first error instead of all of them.
+    pub fn resolve_expression(&mut self, expr: &Expr) -> Result<(), Error> {
+        self.resolve_expr(expr);
+        match std::mem::take(&mut self.diagnostics).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn resolve_block(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_stmt(statement);
+        }
+    }
+
+    fn resolve_stmt(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Block(statements, _span) => {
+                self.begin_scope();
+                self.resolve_block(statements);
+                self.end_scope();
+            }
+            Stmt::Class(name, superclass, methods) => self.resolve_class(name, superclass, methods),
+            Stmt::Expression(expr) | Stmt::Print(expr, _) => self.resolve_expr(expr),
+            Stmt::Function(name, params, body) => {
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(params, body, FunctionKind::Function);
+            }
+            Stmt::If(condition, then_branch, else_branch, _keyword) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::Return(keyword, value) => {
+                if self.current_function.is_none() {
+                    self.error(
+                        keyword,
+                        ErrorCode::ReturnAtTopLevel,
+                        "Can't return from top-level code.",
+                    );
+                }
+                if let Some(value) = value {
+                    if self.current_function == Some(FunctionKind::Initializer) {
+                        self.error(
+                            keyword,
+                            ErrorCode::ReturnValueFromInitializer,
+                            "Can't return a value from an initializer.",
+                        );
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Var(name, initializer) => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::While(condition, body, _keyword) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            }
+        }
+    }
+
+    fn resolve_class(&mut self, name: &Token, superclass: &Option<Expr>, methods: &[Stmt]) {
+        let enclosing_class = self.current_class;
+        self.current_class = Some(ClassKind::Class);
+
+        self.declare(name);
+        self.define(name);
+
+        if let Some(superclass) = superclass {
+            if let Expr::Variable(superclass_name, _depth) = superclass {
+                if superclass_name.lexeme == name.lexeme {
+                    self.error(
+                        superclass_name,
+                        ErrorCode::InheritFromSelf,
+                        "A class can't inherit from itself.",
+                    );
+                }
+            }
+            self.current_class = Some(ClassKind::Subclass);
+            self.resolve_expr(superclass);
+
+            self.begin_scope();
+            self.bind("super");
+        }
+
+        self.begin_scope();
+        self.bind("this");
+
+        for method in methods {
+            if let Stmt::Function(method_name, params, body) = method {
+                let kind = if method_name.lexeme == "init" {
+                    FunctionKind::Initializer
+                } else {
+                    FunctionKind::Method
+                };
+                self.resolve_function(params, body, kind);
+            }
+        }
+
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        self.current_class = enclosing_class;
+    }
+
+    fn resolve_function(&mut self, params: &[Token], body: &[Stmt], kind: FunctionKind) {
+        let enclosing_function = self.current_function;
+        self.current_function = Some(kind);
+
+        self.begin_scope();
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_block(body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ternary(condition, first, second, _operator) => {
+                self.resolve_expr(condition);
+                self.resolve_expr(first);
+                self.resolve_expr(second);
+            }
+            Expr::Binary(left, _operator, right) | Expr::Logical(left, _operator, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Call(callee, _paren, arguments) => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            Expr::Get(object, _name) => self.resolve_expr(object),
+            Expr::Grouping(expr, _) | Expr::Unary(_, expr) => self.resolve_expr(expr),
+            Expr::Index(object, _bracket, index) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::IndexSet(object, _bracket, index, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::List(elements, _span) => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Map(entries, _span) => {
+                for (key, _colon, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::LiteralExpr(..) => {}
+            Expr::Set(object, _name, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::Super(keyword, _method, depth) => match self.current_class {
+                None => self.error(
+                    keyword,
+                    ErrorCode::SuperOutsideClass,
+                    "Can't use 'super' outside of a class.",
+                ),
+                Some(ClassKind::Class) => self.error(
+                    keyword,
+                    ErrorCode::SuperWithoutSuperclass,
+                    "Can't use 'super' in a class with no superclass.",
+                ),
+                Some(ClassKind::Subclass) => self.resolve_local(keyword, depth),
+            },
+            Expr::This(keyword, depth) => {
+                if self.current_class.is_none() {
+                    self.error(
+                        keyword,
+                        ErrorCode::ThisOutsideClass,
+                        "Can't use 'this' outside of a class.",
+                    );
+                }
+                self.resolve_local(keyword, depth);
+            }
+            Expr::Variable(name, depth) => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(&name.lexeme) == Some(&false) {
+                        self.error(
+                            name,
+                            ErrorCode::ReadInOwnInitializer,
+                            "Can't read local variable in its own initializer.",
+                        );
+                    }
+                }
+                self.resolve_local(name, depth);
+            }
+            Expr::Assign { name, value, depth } => {
+                self.resolve_expr(value);
+                self.resolve_local(name, depth);
+            }
+        }
+    }
+
+    fn resolve_local(&self, name: &Token, depth: &Depth) {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme));
+        depth.set(found);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let duplicate = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.lexeme.clone(), false).is_some(),
+            None => false,
+        };
+        if duplicate {
+            self.error(
+                name,
+                ErrorCode::DuplicateVariable,
+                "Already a variable with this name in this scope.",
+            );
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn bind(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(intern(name), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, code: ErrorCode, message: &str) {
+        self.diagnostics.push(Error::CompileTimeError {
+            token: Some(token.clone()),
+            code,
+            message: String::from(message),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::test_support::scan;
+
+    fn resolve_err(source: &str) -> String {
+        let (statements, _errors) = Parser::new(scan(source)).parse();
+        messages(&Resolver::new().resolve(&statements))
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| panic!("expected a compile time error for: {}", source))
+    }
+
+    fn messages(errors: &[Error]) -> Vec<String> {
+        errors
+            .iter()
+            .map(|error| match error {
+                Error::CompileTimeError { message, .. } => message.clone(),
+                _ => panic!("expected a compile time error"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_static_errors() {
+        assert_eq!(
+            resolve_err("{ var a = a; }"),
+            "Can't read local variable in its own initializer."
+        );
+        assert_eq!(
+            resolve_err("fun f() { var a = 1; var a = 2; }"),
+            "Already a variable with this name in this scope."
+        );
+        assert_eq!(
+            resolve_err("return 1;"),
+            "Can't return from top-level code."
+        );
+        assert_eq!(
+            resolve_err("class A { init() { return 1; } }"),
+            "Can't return a value from an initializer."
+        );
+        assert_eq!(
+            resolve_err("print this;"),
+            "Can't use 'this' outside of a class."
+        );
+        assert_eq!(
+            resolve_err("fun f() { super.g(); }"),
+            "Can't use 'super' outside of a class."
+        );
+        assert_eq!(
+            resolve_err("class A { f() { super.f(); } }"),
+            "Can't use 'super' in a class with no superclass."
+        );
+        assert_eq!(
+            resolve_err("class A < A {}"),
+            "A class can't inherit from itself."
+        );
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let source = "{ var a = a; var a = 1; }\nreturn 1;\nprint this;";
+        let (statements, _errors) = Parser::new(scan(source)).parse();
+
+        assert_eq!(
+            messages(&Resolver::new().resolve(&statements)),
+            vec![
+                "Can't read local variable in its own initializer.",
+                "Already a variable with this name in this scope.",
+                "Can't return from top-level code.",
+                "Can't use 'this' outside of a class.",
+            ]
+        );
+    }
+
+    #[test]
+    fn records_depths() {
+        let (statements, _errors) = Parser::new(scan("var g; { var a; { a; g; } }")).parse();
+        assert!(Resolver::new().resolve(&statements).is_empty());
+
+        let inner = match &statements[1] {
+            Stmt::Block(outer, _) => match &outer[1] {
+                Stmt::Block(inner, _) => inner,
+                _ => panic!("expected inner block"),
+            },
+            _ => panic!("expected outer block"),
+        };
+        let depth_of = |stmt: &Stmt| match stmt {
+            Stmt::Expression(expr) => match &**expr {
+                Expr::Variable(_, depth) => depth.get(),
+                _ => panic!("expected a variable"),
+            },
+            _ => panic!("expected an expression statement"),
+        };
+        assert_eq!(depth_of(&inner[0]), Some(1));
+        assert_eq!(depth_of(&inner[1]), None);
+    }
+}
@@ -1,5 +1,9 @@
 use crate::error::Error::{self, CompileTimeError};
-use crate::token::{Literal, Token, TokenType, TokenType::*};
+use crate::error::ErrorCode;
+use crate::interner::intern;
+use crate::token::{Literal, Span, Token, TokenType, TokenType::*};
+use crate::typer::NumberMode;
+use std::rc::Rc;
 
 pub struct Scanner {
     source: String,
@@ -7,26 +11,51 @@ pub struct Scanner {
     start: usize,
     current: usize,
     line: usize,
+    // offset of the first character on the current line, for columns
+    line_start: usize,
+    start_line: usize,
+    start_column: usize,
+    numbers: NumberMode,
+    diagnostics: Vec<Error>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Scanner {
+        Self::with_numbers(source, NumberMode::Float)
+    }
+
+    /// A scanner whose number literals follow `numbers`.
+    pub fn with_numbers(source: String, numbers: NumberMode) -> Scanner {
         Scanner {
             source,
             tokens: vec![],
             start: 0,
             current: 0,
-            line: 0,
+            line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            numbers,
+            diagnostics: Vec::new(),
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Error> {
+    /// Scans the whole source, skipping past characters and literals it
+    /// can't make sense of so that every lexical error is reported. The
+    /// tokens are still worth parsing when there are errors, to find the
+    /// syntax errors that follow.
+    pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<Error>) {
         while !self.is_at_end() {
             self.start = self.current;
-            self.scan_token()?
+            self.start_line = self.line;
+            self.start_column = self.column();
+            if let Err(error) = self.scan_token() {
+                self.diagnostics.push(error);
+            }
         }
-        self.tokens.push(Token::new(EOF, "EOF", None, self.line));
-        Ok(self.tokens)
+        let eof = Span::new(self.current, self.current, self.line, self.column());
+        self.tokens.push(Token::with_span(EOF, "EOF", None, eof));
+        (self.tokens.clone(), std::mem::take(&mut self.diagnostics))
     }
 
     pub fn scan_token(&mut self) -> Result<(), Error> {
@@ -37,12 +66,15 @@ impl Scanner {
             ')' => self.add_token(RIGHT_PAREN, None),
             '{' => self.add_token(LEFT_BRACE, None),
             '}' => self.add_token(RIGHT_BRACE, None),
+            '[' => self.add_token(LEFT_BRACKET, None),
+            ']' => self.add_token(RIGHT_BRACKET, None),
             ',' => self.add_token(COMMA, None),
             '.' => self.add_token(DOT, None),
             '-' => self.add_token(MINUS, None),
             '+' => self.add_token(PLUS, None),
             ';' => self.add_token(SEMICOLON, None),
             '*' => self.add_token(STAR, None),
+            '%' => self.add_token(PERCENT, None),
             ':' => self.add_token(COLON, None),
             '?' => self.add_token(QUESTION, None),
             '!' => {
@@ -69,6 +101,10 @@ impl Scanner {
                 };
                 self.add_token(tok, None);
             }
+            '~' if self.peek_next() == '/' => {
+                self.advance();
+                self.add_token(TILDE_SLASH, None);
+            }
             '/' => {
                 if self.peek_next() == '/' {
                     while self.peek() != '\n' && !self.is_at_end() {
@@ -81,21 +117,22 @@ impl Scanner {
                 }
             }
             ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            '\n' => {}
             '"' => self.string()?,
             _ => {
                 if self.is_digit(c) {
-                    self.number();
+                    self.number()?;
                 } else if self.is_alpha(c) {
                     self.identifier();
                 } else {
                     return Err(CompileTimeError {
-                        token: Some(Token::new(
+                        token: Some(Token::with_span(
                             TokenType::NIL,
-                            &c.clone().to_string().as_str(),
+                            c.to_string().as_str(),
                             None,
-                            self.line,
+                            self.span(),
                         )),
+                        code: ErrorCode::UnexpectedCharacter,
                         message: String::from("Unexpected character"),
                     });
                 }
@@ -117,7 +154,7 @@ impl Scanner {
     }
 
     fn is_alpha(&self, c: char) -> bool {
-        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || (c == '_')
+        c.is_ascii_alphabetic() || c == '_'
     }
 
     fn identifier(&mut self) {
@@ -140,7 +177,8 @@ impl Scanner {
                 self.advance();
             }
 
-            if self.peek() == '.' {
+            // `1.` and `1.foo` leave the dot for the parser
+            if self.peek() == '.' && self.is_digit(self.char_after_peek()) {
                 self.advance();
 
                 while !self.is_at_end() && self.is_digit(self.peek()) {
@@ -149,30 +187,49 @@ impl Scanner {
             }
 
             if self.is_alpha(self.peek_next()) {
+                let span = Span::new(self.current, self.current + 1, self.line, self.column());
                 return Err(CompileTimeError {
-                    token: Some(Token::new(
+                    token: Some(Token::with_span(
                         TokenType::NIL,
-                        &self.peek_next().clone().to_string(),
+                        &self.peek_next().to_string(),
                         None,
-                        self.line,
+                        span,
                     )),
+                    code: ErrorCode::InvalidNumber,
                     message: String::from("Unexpected character. check your number"),
                 });
             }
         }
 
-        // this is to normalize the advanced current value
-        let num = match self.source[self.start..(self.current)]
-            .trim()
-            .parse::<f64>()
-            .ok()
-        {
-            None => 0.0,
-            Some(num) => num,
+        let text = self.source[self.start..self.current].trim();
+        let literal = match (self.numbers, text.contains('.')) {
+            (NumberMode::Float, true) => text.parse().ok().map(Literal::Number),
+            (NumberMode::Float, false) => text.parse().ok().map(Literal::Int),
+            (NumberMode::Exact, true) => {
+                text.parse().ok().map(|num| Literal::Decimal(Rc::new(num)))
+            }
+            (NumberMode::Exact, false) => {
+                text.parse().ok().map(|num| Literal::BigInt(Rc::new(num)))
+            }
         };
+        match literal {
+            Some(literal) => {
+                self.add_token(NUMBER, Some(literal));
+                Ok(())
+            }
+            None => Err(CompileTimeError {
+                token: Some(Token::with_span(NIL, text, None, self.span())),
+                code: ErrorCode::InvalidNumber,
+                message: String::from("Number literal is too large for an integer"),
+            }),
+        }
+    }
 
-        self.add_token(NUMBER, Some(Literal::Number(num)));
-        Ok(())
+    fn char_after_peek(&self) -> char {
+        if self.current + 1 >= self.source.len() {
+            return '\0';
+        }
+        self.get_char_at(self.current + 1)
     }
 
     fn peek_next(&self) -> char {
@@ -187,27 +244,25 @@ impl Scanner {
     }
 
     fn is_digit(&self, c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
     }
 
     fn string(&mut self) -> Result<(), Error> {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
             self.advance();
         }
         if self.is_at_end() {
             return Err(CompileTimeError {
-                token: Some(Token::new(NIL, "EOF", None, self.line)),
+                token: Some(Token::with_span(NIL, "EOF", None, self.span())),
+                code: ErrorCode::UnterminatedString,
                 message: String::from("Unterminated string"),
             });
         }
 
         self.advance();
 
-        let value = &self.source[self.start + 1..self.current - 1].to_string();
-        self.add_token(STRING, Some(Literal::Str(value.to_string())));
+        let value = intern(&self.source[self.start + 1..self.current - 1]);
+        self.add_token(STRING, Some(Literal::Str(value)));
         Ok(())
     }
 
@@ -231,17 +286,32 @@ impl Scanner {
         true
     }
 
+    /// Every character goes through here, so this is the one place that
+    /// keeps line and column bookkeeping up to date.
     fn advance(&mut self) -> char {
         self.current += 1;
-        self.source.chars().nth(self.current - 1).unwrap()
+        let c = self.source.chars().nth(self.current - 1).unwrap();
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.current;
+        }
+        c
+    }
+
+    fn column(&self) -> usize {
+        self.current - self.line_start + 1
+    }
+
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current, self.start_line, self.start_column)
     }
 
     fn add_token(&mut self, token_type: TokenType, literal: Option<Literal>) {
         let text = self.source[self.start..self.current].to_string();
         let text = text.trim();
-        let line = self.line;
+        let span = self.span();
         self.tokens
-            .push(Token::new(token_type, &text, literal, line));
+            .push(Token::with_span(token_type, text, literal, span));
     }
 
     fn is_at_end(&self) -> bool {
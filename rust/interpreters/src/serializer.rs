@@ -0,0 +1,627 @@
+//! Compiled scripts as bytes, so they can be run without scanning or
+//! parsing them again.
+//!
+//! A file starts with `MAGIC`, the format `VERSION` (u16) and a checksum
+//! (u32) of everything after it, followed by the script's function. A
+//! function is its name, arity and upvalue count, then its chunk: the
+//! constant table, the code, the line table and the sites. Integers are
+//! big-endian, counts and offsets are u32 and strings are a length followed
+//! by UTF-8.
+
+use crate::chunk::{Chunk, Constant, Function, OpCode, Site};
+use crate::error::{Error, ErrorCode};
+use crate::interner::intern;
+use crate::token::{Span, Token, TokenType};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::BigDecimal;
+use std::rc::Rc;
+use std::str::FromStr;
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+/// Bumped whenever the layout or the instruction set changes.
+pub const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 10;
+
+const INT: u8 = 0;
+const NUMBER: u8 = 1;
+const BIG_INT: u8 = 2;
+const DECIMAL: u8 = 3;
+const STR: u8 = 4;
+const FUNCTION: u8 = 5;
+
+/// Encodes `script` and everything it contains.
+pub fn serialize(script: &Function) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_function(&mut body, script);
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_be_bytes());
+    bytes.extend_from_slice(&checksum(&body).to_be_bytes());
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+/// Decodes a script written by `serialize`. Files from another version,
+/// damaged files and code the VM could not run safely are all rejected.
+pub fn deserialize(bytes: &[u8]) -> Result<Rc<Function>, Error> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err(invalid("not a compiled script"));
+    }
+    let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(Error::CompileTimeError {
+            token: None,
+            code: ErrorCode::BytecodeVersionMismatch,
+            message: format!(
+                "compiled script is format version {}, but this build reads version {}",
+                version, VERSION
+            ),
+        });
+    }
+    let body = &bytes[HEADER_LEN..];
+    if checksum(body) != u32::from_be_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]) {
+        return Err(invalid("checksum mismatch, the file is damaged"));
+    }
+
+    let mut reader = Reader { bytes: body, at: 0 };
+    let script = reader.function()?;
+    if reader.at != body.len() {
+        return Err(invalid("trailing bytes after the script"));
+    }
+    if script.arity != 0 {
+        return Err(invalid("the script takes arguments"));
+    }
+    validate(&script)?;
+    Ok(Rc::new(script))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::CompileTimeError {
+        token: None,
+        code: ErrorCode::InvalidBytecode,
+        message: format!("invalid compiled script: {}", message),
+    }
+}
+
+// FNV-1a
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn write_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_be_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, string: &str) {
+    write_u32(out, string.len());
+    out.extend_from_slice(string.as_bytes());
+}
+
+fn write_function(out: &mut Vec<u8>, function: &Function) {
+    write_str(out, &function.name);
+    write_u32(out, function.arity);
+    write_u32(out, function.upvalues);
+    write_chunk(out, &function.chunk);
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    write_u32(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Int(num) => {
+                out.push(INT);
+                out.extend_from_slice(&num.to_be_bytes());
+            }
+            Constant::Number(num) => {
+                out.push(NUMBER);
+                out.extend_from_slice(&num.to_bits().to_be_bytes());
+            }
+            Constant::BigInt(num) => {
+                out.push(BIG_INT);
+                write_str(out, &num.to_string());
+            }
+            Constant::Decimal(num) => {
+                out.push(DECIMAL);
+                write_str(out, &num.to_string());
+            }
+            Constant::Str(string) => {
+                out.push(STR);
+                write_str(out, string);
+            }
+            Constant::Function(function) => {
+                out.push(FUNCTION);
+                write_function(out, function);
+            }
+        }
+    }
+
+    write_u32(out, chunk.code.len());
+    out.extend_from_slice(&chunk.code);
+
+    write_u32(out, chunk.lines.len());
+    for &(offset, line) in &chunk.lines {
+        write_u32(out, offset);
+        write_u32(out, line);
+    }
+
+    write_u32(out, chunk.sites.len());
+    for site in &chunk.sites {
+        let token = &site.token;
+        write_str(out, token.token_type.to_string());
+        write_str(out, &token.lexeme);
+        write_u32(out, token.line);
+        for span in [token.span, site.span] {
+            write_u32(out, span.start);
+            write_u32(out, span.end);
+            write_u32(out, span.line);
+            write_u32(out, span.column);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self
+            .at
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        let bytes = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn str(&mut self) -> Result<String, Error> {
+        let len = self.u32()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("string is not valid UTF-8"))
+    }
+
+    // every item takes at least a byte, so a damaged count fails here
+    // instead of looping for billions of items
+    fn count(&mut self) -> Result<usize, Error> {
+        let count = self.u32()?;
+        if count > self.bytes.len() - self.at {
+            return Err(invalid("count runs past the end of the file"));
+        }
+        Ok(count)
+    }
+
+    fn span(&mut self) -> Result<Span, Error> {
+        Ok(Span::new(
+            self.u32()?,
+            self.u32()?,
+            self.u32()?,
+            self.u32()?,
+        ))
+    }
+
+    fn function(&mut self) -> Result<Function, Error> {
+        Ok(Function {
+            name: self.str()?,
+            arity: self.u32()?,
+            upvalues: self.u32()?,
+            chunk: self.chunk()?,
+        })
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, Error> {
+        let mut chunk = Chunk::default();
+
+        for _ in 0..self.count()? {
+            let constant = match self.u8()? {
+                INT => Constant::Int(self.u64()? as i64),
+                NUMBER => Constant::Number(f64::from_bits(self.u64()?)),
+                BIG_INT => Constant::BigInt(Rc::new(
+                    BigInt::from_str(&self.str()?).map_err(|_| invalid("malformed integer"))?,
+                )),
+                DECIMAL => Constant::Decimal(Rc::new(
+                    BigDecimal::from_str(&self.str()?).map_err(|_| invalid("malformed decimal"))?,
+                )),
+                STR => Constant::Str(intern(&self.str()?)),
+                FUNCTION => Constant::Function(Rc::new(self.function()?)),
+                tag => return Err(invalid(&format!("unknown constant kind {}", tag))),
+            };
+            chunk.constants.push(constant);
+        }
+
+        let len = self.u32()?;
+        chunk.code = self.take(len)?.to_vec();
+
+        for _ in 0..self.count()? {
+            chunk.lines.push((self.u32()?, self.u32()?));
+        }
+
+        for _ in 0..self.count()? {
+            let name = self.str()?;
+            let token_type = TokenType::from_name(&name)
+                .ok_or_else(|| invalid(&format!("unknown token type {}", name)))?;
+            let lexeme = self.str()?;
+            let line = self.u32()?;
+            let token = Token {
+                line,
+                ..Token::with_span(token_type, &lexeme, None, self.span()?)
+            };
+            let span = self.span()?;
+            chunk.sites.push(Site { token, span });
+        }
+        Ok(chunk)
+    }
+}
+
+/// Checks that every instruction is well formed: known opcodes, operands
+/// inside the code, indexes inside their tables, jumps landing on
+/// instructions and a `Return` at the end. Then checks that the stack holds
+/// whatever each instruction reads.
+fn validate(function: &Function) -> Result<(), Error> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let at = |offset: usize, problem: &str| {
+        invalid(&format!("{} at {} in '{}'", problem, offset, function.name))
+    };
+
+    let mut starts = Vec::new();
+    let mut targets = Vec::new();
+    let mut last = None;
+    let mut offset = 0;
+    while offset < code.len() {
+        starts.push(offset);
+        let op = OpCode::from_byte(code[offset]).ok_or_else(|| at(offset, "unknown opcode"))?;
+        let mut next = offset + 1 + op.operand_len();
+        if next > code.len() {
+            return Err(at(offset, "truncated instruction"));
+        }
+        let byte = |n: usize| code[offset + n] as usize;
+        let u16_at = |n: usize| chunk.read_u16(offset + n) as usize;
+        let site = |n: usize| {
+            if u16_at(n) < chunk.sites.len() {
+                Ok(())
+            } else {
+                Err(at(offset, "site out of range"))
+            }
+        };
+
+        match op {
+            OpCode::Constant => match chunk.constants.get(u16_at(1)) {
+                Some(Constant::Function(_)) | None => return Err(at(offset, "bad constant")),
+                Some(_) => {}
+            },
+            OpCode::DefineGlobal => match chunk.constants.get(u16_at(1)) {
+                Some(Constant::Str(_)) => {}
+                _ => return Err(at(offset, "global name is not a string")),
+            },
+            OpCode::GetUpvalue | OpCode::SetUpvalue if byte(1) >= function.upvalues => {
+                return Err(at(offset, "upvalue out of range"));
+            }
+            OpCode::Jump | OpCode::JumpIfFalse => targets.push((offset, next + u16_at(1))),
+            OpCode::Loop => match next.checked_sub(u16_at(1)) {
+                Some(target) => targets.push((offset, target)),
+                None => return Err(at(offset, "loop before the start of the code")),
+            },
+            OpCode::Call => site(2)?,
+            OpCode::Closure => {
+                let upvalues = match chunk.constants.get(u16_at(1)) {
+                    Some(Constant::Function(closed)) => closed.upvalues,
+                    _ => return Err(at(offset, "closure of a non-function")),
+                };
+                for _ in 0..upvalues {
+                    if next + 2 > code.len() {
+                        return Err(at(offset, "truncated instruction"));
+                    }
+                    let (is_local, index) = (code[next], code[next + 1] as usize);
+                    if is_local > 1 || (is_local == 0 && index >= function.upvalues) {
+                        return Err(at(offset, "bad upvalue capture"));
+                    }
+                    next += 2;
+                }
+            }
+            OpCode::Class => {
+                site(1)?;
+                if byte(4) > 1 {
+                    return Err(at(offset, "bad superclass flag"));
+                }
+            }
+            OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::GetIndex
+            | OpCode::SetIndex
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::IntDivide
+            | OpCode::Modulo
+            | OpCode::Negate
+            | OpCode::Ternary
+            | OpCode::Print
+            | OpCode::MapEntry => site(1)?,
+            _ => {}
+        }
+        last = Some(op);
+        offset = next;
+    }
+
+    if last != Some(OpCode::Return) {
+        return Err(at(code.len(), "missing return"));
+    }
+    for (offset, target) in targets {
+        if starts.binary_search(&target).is_err() {
+            return Err(at(offset, "jump into the middle of an instruction"));
+        }
+    }
+    check_stack(function)?;
+    for constant in &chunk.constants {
+        if let Constant::Function(inner) = constant {
+            validate(inner)?;
+        }
+    }
+    Ok(())
+}
+
+/// Follows every path through code `validate` has found well formed,
+/// tracking how many values the function has on the stack. Instructions may
+/// only pop values that are there and only use local slots below the top,
+/// and an instruction must see the same depth whichever way it's reached.
+fn check_stack(function: &Function) -> Result<(), Error> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let at = |offset: usize, problem: &str| {
+        invalid(&format!("{} at {} in '{}'", problem, offset, function.name))
+    };
+
+    let mut depths = vec![None; code.len()];
+    // slot 0 holds the function or receiver, followed by the arguments
+    let mut pending = vec![(0, function.arity + 1)];
+    while let Some((offset, depth)) = pending.pop() {
+        match depths[offset] {
+            Some(seen) if seen == depth => continue,
+            Some(_) => return Err(at(offset, "stack depth differs between paths")),
+            None => depths[offset] = Some(depth),
+        }
+        let op = OpCode::from_byte(code[offset]).expect("opcodes are validated");
+        let byte = |n: usize| code[offset + n] as usize;
+        let u16_at = |n: usize| chunk.read_u16(offset + n) as usize;
+        let local = |slot: usize| {
+            if slot < depth {
+                Ok(())
+            } else {
+                Err(at(offset, "local slot out of range"))
+            }
+        };
+
+        let mut next = offset + 1 + op.operand_len();
+        let (pops, pushes) = match op {
+            OpCode::Constant
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetGlobal
+            | OpCode::GetUpvalue
+            | OpCode::Map => (0, 1),
+            OpCode::GetLocal => {
+                local(byte(1))?;
+                (0, 1)
+            }
+            OpCode::SetLocal => {
+                local(byte(1))?;
+                (1, 1)
+            }
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print | OpCode::CloseUpvalue => (1, 0),
+            OpCode::SetGlobal
+            | OpCode::SetUpvalue
+            | OpCode::GetProperty
+            | OpCode::Negate
+            | OpCode::Not
+            | OpCode::JumpIfFalse => (1, 1),
+            OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::GetIndex
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::IntDivide
+            | OpCode::Modulo => (2, 1),
+            OpCode::SetIndex | OpCode::Ternary | OpCode::MapEntry => (3, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0),
+            OpCode::Call => (byte(1) + 1, 1),
+            OpCode::Closure => {
+                if let Some(Constant::Function(closed)) = chunk.constants.get(u16_at(1)) {
+                    for _ in 0..closed.upvalues {
+                        // a function that refers to itself captures the slot
+                        // its closure is about to be pushed into
+                        if code[next] == 1 && code[next + 1] as usize > depth {
+                            return Err(at(offset, "local slot out of range"));
+                        }
+                        next += 2;
+                    }
+                }
+                (0, 1)
+            }
+            OpCode::Return => (1, 0),
+            // the superclass is read where it is and stays on the stack
+            OpCode::Class => (byte(3) + byte(4), byte(4) + 1),
+            OpCode::List => (u16_at(1), 1),
+        };
+        if pops > depth {
+            return Err(at(offset, "stack underflow"));
+        }
+        let depth = depth - pops + pushes;
+
+        match op {
+            OpCode::Return => {}
+            OpCode::Jump => pending.push((next + u16_at(1), depth)),
+            OpCode::Loop => pending.push((next - u16_at(1), depth)),
+            OpCode::JumpIfFalse => {
+                pending.push((next + u16_at(1), depth));
+                pending.push((next, depth));
+            }
+            _ => pending.push((next, depth)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::test_support::parse;
+
+    fn compile(source: &str) -> Rc<Function> {
+        Compiler::compile(&parse(source)).unwrap()
+    }
+
+    fn code(error: Error) -> ErrorCode {
+        error.code().unwrap()
+    }
+
+    const SOURCE: &str = "
+        class Counter {
+            init(step) { this.step = step; this.n = 0; }
+            bump() { this.n = this.n + this.step; return this; }
+        }
+        fun make() { var c = Counter(2.5); fun tick() { return c.bump().n; } return tick; }
+        var tick = make();
+        print [tick(), tick(), \"done\"];";
+
+    #[test]
+    fn round_trips() {
+        let script = compile(SOURCE);
+        let loaded = deserialize(&serialize(&script)).unwrap();
+        assert_eq!(loaded.disassemble(), script.disassemble());
+        assert_eq!(serialize(&loaded), serialize(&script));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(
+            code(deserialize(b"print 1;").unwrap_err()),
+            ErrorCode::InvalidBytecode
+        );
+        assert_eq!(
+            code(deserialize(b"").unwrap_err()),
+            ErrorCode::InvalidBytecode
+        );
+
+        let mut bytes = serialize(&compile(SOURCE));
+        bytes[5] += 1;
+        assert_eq!(
+            code(deserialize(&bytes).unwrap_err()),
+            ErrorCode::BytecodeVersionMismatch
+        );
+    }
+
+    #[test]
+    fn rejects_damage() {
+        let bytes = serialize(&compile(SOURCE));
+        for at in HEADER_LEN..bytes.len() {
+            let mut damaged = bytes.clone();
+            damaged[at] ^= 0x40;
+            assert_eq!(
+                code(deserialize(&damaged).unwrap_err()),
+                ErrorCode::InvalidBytecode
+            );
+        }
+        for len in 0..bytes.len() {
+            assert!(deserialize(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_code() {
+        let mut script = Function {
+            name: String::from("<script>"),
+            arity: 0,
+            upvalues: 0,
+            chunk: Chunk::default(),
+        };
+        script.chunk.write(OpCode::Loop as u8, 1);
+        script.chunk.write(0, 1);
+        script.chunk.write(1, 1);
+        script.chunk.write(OpCode::Nil as u8, 1);
+        script.chunk.write(OpCode::Return as u8, 1);
+
+        let error = deserialize(&serialize(&script)).unwrap_err();
+        assert!(error.message().contains("jump into the middle"));
+
+        script.chunk.code.pop();
+        let error = deserialize(&serialize(&script)).unwrap_err();
+        assert!(error.message().contains("missing return"));
+    }
+
+    #[test]
+    fn rejects_code_that_reaches_past_the_stack() {
+        let function = |arity: usize, code: &[u8]| {
+            let mut function = Function {
+                name: String::from("<script>"),
+                arity,
+                upvalues: 0,
+                chunk: Chunk::default(),
+            };
+            for &byte in code {
+                function.chunk.write(byte, 1);
+            }
+            deserialize(&serialize(&function)).map(|_| ())
+        };
+        let get = OpCode::GetLocal as u8;
+        let set = OpCode::SetLocal as u8;
+        let ret = OpCode::Return as u8;
+
+        assert!(function(0, &[get, 0, ret]).is_ok());
+        let error = function(0, &[get, 200, ret]).unwrap_err();
+        assert_eq!(error.code(), Some(ErrorCode::InvalidBytecode));
+        assert!(error.message().contains("local slot out of range"));
+        let error = function(0, &[OpCode::Nil as u8, set, 2, ret]).unwrap_err();
+        assert!(error.message().contains("local slot out of range"));
+        let error = function(0, &[OpCode::Pop as u8, ret]).unwrap_err();
+        assert!(error.message().contains("stack underflow"));
+        let error = function(1, &[OpCode::Nil as u8, ret]).unwrap_err();
+        assert!(error.message().contains("takes arguments"));
+
+        // one path through the jump leaves an extra value behind
+        let nil = OpCode::Nil as u8;
+        let jump = OpCode::JumpIfFalse as u8;
+        let error = function(0, &[nil, jump, 0, 1, nil, ret]).unwrap_err();
+        assert!(error.message().contains("stack depth differs"));
+    }
+}
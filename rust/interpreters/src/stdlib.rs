@@ -0,0 +1,652 @@
+use crate::arithmetic;
+use crate::error::{Error, ErrorCode};
+use crate::interpreter::Interpreter;
+use crate::lox_map::{LoxMap, MapKey};
+use crate::typer::{FromTyper, NumberMode, Typer};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, Signed};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Installs the builtins every script can use into the interpreter's globals.
+pub fn install(interpreter: &Interpreter) {
+    interpreter.define_native("clock", 0, |_, _| Ok(Typer::Number(now())));
+
+    install_math(interpreter);
+    install_strings(interpreter);
+    install_lists(interpreter);
+    install_maps(interpreter);
+    install_io_stubs(interpreter);
+
+    interpreter.define_native("type_of", 1, |_, arguments| {
+        Ok(Typer::from(arguments[0].type_name()))
+    });
+    interpreter.define_native("to_string", 1, |_, arguments| {
+        Ok(Typer::from(arguments[0].to_string()))
+    });
+    interpreter.define_native("to_number", 1, |interpreter, arguments| {
+        match &arguments[0] {
+            num @ Typer::Int(_)
+            | num @ Typer::Number(_)
+            | num @ Typer::BigInt(_)
+            | num @ Typer::Decimal(_) => Ok(num.clone()),
+            // unparsable text is nil rather than an error so scripts can test for it
+            Typer::Str(st) => {
+                let st = st.trim();
+                Ok(match interpreter.number_mode() {
+                    NumberMode::Float => match st.parse::<i64>() {
+                        Ok(num) => Typer::Int(num),
+                        Err(_) => st.parse().map_or(Typer::Nil, Typer::Number),
+                    },
+                    NumberMode::Exact => match st.parse::<BigInt>() {
+                        Ok(num) => Typer::from(num),
+                        Err(_) => st.parse::<BigDecimal>().map_or(Typer::Nil, Typer::from),
+                    },
+                })
+            }
+            other => Err(invalid(format!(
+                "Can't convert {} to a number.",
+                other.type_name()
+            ))),
+        }
+    });
+}
+
+fn install_math(interpreter: &Interpreter) {
+    interpreter.define_native("sqrt", 1, |_, arguments| {
+        Ok(Typer::Number(arg::<f64>("sqrt", &arguments, 0)?.sqrt()))
+    });
+    interpreter.define_native("floor", 1, |_, arguments| {
+        Ok(Typer::Number(arg::<f64>("floor", &arguments, 0)?.floor()))
+    });
+    interpreter.define_native("abs", 1, |_, arguments| match &arguments[0] {
+        Typer::Int(num) => num.checked_abs().map(Typer::Int).ok_or_else(|| {
+            invalid(format!(
+                "The absolute value of {} doesn't fit in an int.",
+                num
+            ))
+        }),
+        Typer::BigInt(num) => Ok(Typer::from(num.abs())),
+        Typer::Decimal(num) => Ok(Typer::from(num.abs())),
+        _ => Ok(Typer::Number(arg::<f64>("abs", &arguments, 0)?.abs())),
+    });
+    interpreter.define_native("pow", 2, |_, arguments| {
+        let base = arg::<f64>("pow", &arguments, 0)?;
+        Ok(Typer::Number(base.powf(arg("pow", &arguments, 1)?)))
+    });
+    // both keep the kind of the number they pick, so exact numbers stay exact
+    interpreter.define_native("min", 2, |_, arguments| {
+        match arithmetic::compare(&arguments[0], &arguments[1]) {
+            Some(Ordering::Greater) => Ok(arguments[1].clone()),
+            Some(_) => Ok(arguments[0].clone()),
+            None => {
+                let a = arg::<f64>("min", &arguments, 0)?;
+                Ok(Typer::Number(a.min(arg("min", &arguments, 1)?)))
+            }
+        }
+    });
+    interpreter.define_native("max", 2, |_, arguments| {
+        match arithmetic::compare(&arguments[0], &arguments[1]) {
+            Some(Ordering::Less) => Ok(arguments[1].clone()),
+            Some(_) => Ok(arguments[0].clone()),
+            None => {
+                let a = arg::<f64>("max", &arguments, 0)?;
+                Ok(Typer::Number(a.max(arg("max", &arguments, 1)?)))
+            }
+        }
+    });
+
+    // `random` and `random_seed` share one generator per interpreter
+    let state = Rc::new(Cell::new(seed_state(now().to_bits())));
+    let random_state = Rc::clone(&state);
+    interpreter.define_native("random", 0, move |_, _| {
+        Ok(Typer::Number(next_random(&random_state)))
+    });
+    interpreter.define_native("random_seed", 1, move |_, arguments| {
+        let seed = arg::<f64>("random_seed", &arguments, 0)?;
+        state.set(seed_state(seed.to_bits()));
+        Ok(Typer::Nil)
+    });
+}
+
+fn install_strings(interpreter: &Interpreter) {
+    interpreter.define_native("len", 1, |_, arguments| {
+        let st = arg::<String>("len", &arguments, 0)?;
+        Ok(Typer::Int(st.chars().count() as i64))
+    });
+    interpreter.define_native("substring", 3, |_, arguments| {
+        let st = arg::<String>("substring", &arguments, 0)?;
+        let start = index("substring", &arguments, 1)?;
+        let end = index("substring", &arguments, 2)?;
+        let length = st.chars().count();
+        if start > end || end > length {
+            return Err(invalid(format!(
+                "Substring range {}..{} is out of bounds for a string of length {}.",
+                start, end, length
+            )));
+        }
+        Ok(Typer::from(
+            st.chars().skip(start).take(end - start).collect::<String>(),
+        ))
+    });
+    interpreter.define_native("index_of", 2, |_, arguments| {
+        let st = arg::<String>("index_of", &arguments, 0)?;
+        let needle = arg::<String>("index_of", &arguments, 1)?;
+        Ok(Typer::Int(match st.find(&needle) {
+            Some(byte) => st[..byte].chars().count() as i64,
+            None => -1,
+        }))
+    });
+    interpreter.define_native("upper", 1, |_, arguments| {
+        Ok(Typer::from(
+            arg::<String>("upper", &arguments, 0)?.to_uppercase(),
+        ))
+    });
+    interpreter.define_native("lower", 1, |_, arguments| {
+        Ok(Typer::from(
+            arg::<String>("lower", &arguments, 0)?.to_lowercase(),
+        ))
+    });
+    interpreter.define_native("trim", 1, |_, arguments| {
+        Ok(Typer::from(arg::<String>("trim", &arguments, 0)?.trim()))
+    });
+    interpreter.define_native("split", 2, |_, arguments| {
+        let st = arg::<String>("split", &arguments, 0)?;
+        let separator = arg::<String>("split", &arguments, 1)?;
+        if separator.is_empty() {
+            return Err(invalid(String::from("Can't split on an empty separator.")));
+        }
+        Ok(Typer::from(
+            st.split(separator.as_str()).collect::<Vec<_>>(),
+        ))
+    });
+}
+
+fn install_lists(interpreter: &Interpreter) {
+    interpreter.define_native("length", 1, |_, arguments| match &arguments[0] {
+        Typer::List(items) => Ok(Typer::Int(items.borrow().len() as i64)),
+        Typer::Map(map) => Ok(Typer::Int(map.borrow().len() as i64)),
+        other => Err(Error::RuntimeError {
+            token: None,
+            code: ErrorCode::TypeMismatch,
+            message: format!(
+                "Expected list or map but got {} (argument 1 of 'length')",
+                other.type_name()
+            ),
+            trace: Vec::new(),
+        }),
+    });
+    interpreter.define_native("push", 2, |_, arguments| {
+        list_arg("push", &arguments, 0)?
+            .borrow_mut()
+            .push(arguments[1].clone());
+        Ok(Typer::Nil)
+    });
+    interpreter.define_native("pop", 1, |_, arguments| {
+        match list_arg("pop", &arguments, 0)?.borrow_mut().pop() {
+            Some(item) => Ok(item),
+            None => Err(out_of_bounds(String::from("Can't pop from an empty list."))),
+        }
+    });
+    interpreter.define_native("insert", 3, |_, arguments| {
+        let items = list_arg("insert", &arguments, 0)?;
+        let at = index("insert", &arguments, 1)?;
+        let mut items = items.borrow_mut();
+        // inserting at the end is allowed, so the bound is inclusive
+        if at > items.len() {
+            return Err(out_of_bounds(format!(
+                "Can't insert at {} in a list of length {}.",
+                at,
+                items.len()
+            )));
+        }
+        items.insert(at, arguments[2].clone());
+        Ok(Typer::Nil)
+    });
+    interpreter.define_native("remove", 2, |_, arguments| {
+        if let Typer::Map(map) = &arguments[0] {
+            let key = map_key("remove", &arguments, 1)?;
+            return match map.borrow_mut().remove(&key) {
+                Some(value) => Ok(value),
+                None => Err(key_not_found(&arguments[1])),
+            };
+        }
+        let items = list_arg("remove", &arguments, 0)?;
+        let at = index("remove", &arguments, 1)?;
+        let mut items = items.borrow_mut();
+        if at >= items.len() {
+            return Err(out_of_bounds(format!(
+                "Index {} is out of bounds for a list of length {}.",
+                at,
+                items.len()
+            )));
+        }
+        Ok(items.remove(at))
+    });
+    interpreter.define_native("slice", 3, |_, arguments| {
+        let items = list_arg("slice", &arguments, 0)?;
+        let start = index("slice", &arguments, 1)?;
+        let end = index("slice", &arguments, 2)?;
+        let items = items.borrow();
+        if start > end || end > items.len() {
+            return Err(out_of_bounds(format!(
+                "Slice range {}..{} is out of bounds for a list of length {}.",
+                start,
+                end,
+                items.len()
+            )));
+        }
+        Ok(Typer::List(Rc::new(RefCell::new(
+            items[start..end].to_vec(),
+        ))))
+    });
+}
+
+fn install_maps(interpreter: &Interpreter) {
+    interpreter.define_native("has", 2, |_, arguments| {
+        let map = map_arg("has", &arguments, 0)?;
+        let key = map_key("has", &arguments, 1)?;
+        let has = map.borrow().contains(&key);
+        Ok(Typer::Boolean(has))
+    });
+    interpreter.define_native("keys", 1, |_, arguments| {
+        let map = map_arg("keys", &arguments, 0)?;
+        let keys: Vec<Typer> = map
+            .borrow()
+            .entries()
+            .map(|(key, _)| key.to_typer())
+            .collect();
+        Ok(Typer::from(keys))
+    });
+    interpreter.define_native("values", 1, |_, arguments| {
+        let map = map_arg("values", &arguments, 0)?;
+        let values: Vec<Typer> = map
+            .borrow()
+            .entries()
+            .map(|(_, value)| value.clone())
+            .collect();
+        Ok(Typer::from(values))
+    });
+}
+
+fn map_arg(name: &str, arguments: &[Typer], position: usize) -> Result<Rc<RefCell<LoxMap>>, Error> {
+    match &arguments[position] {
+        Typer::Map(map) => Ok(Rc::clone(map)),
+        other => Err(Error::RuntimeError {
+            token: None,
+            code: ErrorCode::TypeMismatch,
+            message: format!(
+                "Expected map but got {} (argument {} of '{}')",
+                other.type_name(),
+                position + 1,
+                name
+            ),
+            trace: Vec::new(),
+        }),
+    }
+}
+
+fn map_key(name: &str, arguments: &[Typer], position: usize) -> Result<MapKey, Error> {
+    MapKey::from_typer(&arguments[position]).ok_or_else(|| Error::RuntimeError {
+        token: None,
+        code: ErrorCode::TypeMismatch,
+        message: format!(
+            "Map keys must be strings, numbers or booleans (argument {} of '{}')",
+            position + 1,
+            name
+        ),
+        trace: Vec::new(),
+    })
+}
+
+fn key_not_found(key: &Typer) -> Error {
+    Error::RuntimeError {
+        token: None,
+        code: ErrorCode::KeyNotFound,
+        message: format!("Key '{}' is not in the map.", key),
+        trace: Vec::new(),
+    }
+}
+
+/// The list itself rather than a copy, so builtins can change it in place.
+fn list_arg(
+    name: &str,
+    arguments: &[Typer],
+    position: usize,
+) -> Result<Rc<RefCell<Vec<Typer>>>, Error> {
+    match &arguments[position] {
+        Typer::List(items) => Ok(Rc::clone(items)),
+        other => Err(Error::RuntimeError {
+            token: None,
+            code: ErrorCode::TypeMismatch,
+            message: format!(
+                "Expected list but got {} (argument {} of '{}')",
+                other.type_name(),
+                position + 1,
+                name
+            ),
+            trace: Vec::new(),
+        }),
+    }
+}
+
+fn out_of_bounds(message: String) -> Error {
+    Error::RuntimeError {
+        token: None,
+        code: ErrorCode::IndexOutOfBounds,
+        message,
+        trace: Vec::new(),
+    }
+}
+
+const IO_BUILTINS: [(&str, usize); 4] = [
+    ("read_line", 0),
+    ("read_file", 1),
+    ("write_file", 2),
+    ("file_exists", 1),
+];
+
+// scripts are sandboxed until the host opts in, but still get a clear error
+fn install_io_stubs(interpreter: &Interpreter) {
+    for (name, arity) in IO_BUILTINS.iter() {
+        interpreter.define_native(name, *arity, move |_, _| {
+            Err(Error::RuntimeError {
+                token: None,
+                code: ErrorCode::CapabilityDisabled,
+                message: format!("'{}' needs I/O, which is disabled.", name),
+                trace: Vec::new(),
+            })
+        });
+    }
+}
+
+/// Replaces the I/O stubs with builtins that really touch stdin and the
+/// file system.
+pub fn install_io(interpreter: &Interpreter) {
+    interpreter.define_native("read_line", 0, |_, _| {
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).map_err(io_failed)? == 0 {
+            return Ok(Typer::Nil);
+        }
+        Ok(Typer::from(line.trim_end_matches(&['\n', '\r'][..])))
+    });
+    interpreter.define_native("read_file", 1, |_, arguments| {
+        let path = arg::<String>("read_file", &arguments, 0)?;
+        Ok(Typer::from(fs::read_to_string(path).map_err(io_failed)?))
+    });
+    interpreter.define_native("write_file", 2, |_, arguments| {
+        let path = arg::<String>("write_file", &arguments, 0)?;
+        let contents = arg::<String>("write_file", &arguments, 1)?;
+        fs::write(path, contents).map_err(io_failed)?;
+        Ok(Typer::Nil)
+    });
+    interpreter.define_native("file_exists", 1, |_, arguments| {
+        let path = arg::<String>("file_exists", &arguments, 0)?;
+        Ok(Typer::Boolean(Path::new(&path).exists()))
+    });
+}
+
+fn io_failed(error: io::Error) -> Error {
+    Error::RuntimeError {
+        token: None,
+        code: ErrorCode::IoFailed,
+        message: error.to_string(),
+        trace: Vec::new(),
+    }
+}
+
+/// Reads argument `position` of builtin `name`, naming both in type errors.
+fn arg<T: FromTyper>(name: &str, arguments: &[Typer], position: usize) -> Result<T, Error> {
+    T::from_typer(arguments[position].clone()).map_err(|error| match error {
+        Error::RuntimeError {
+            token,
+            code,
+            message,
+            trace,
+        } => Error::RuntimeError {
+            token,
+            code,
+            message: format!(
+                "{} (argument {} of '{}')",
+                message.trim_end_matches('.'),
+                position + 1,
+                name
+            ),
+            trace,
+        },
+        error => error,
+    })
+}
+
+fn index(name: &str, arguments: &[Typer], position: usize) -> Result<usize, Error> {
+    let num = arg::<i64>(name, arguments, position)?;
+    if num < 0 {
+        return Err(invalid(format!(
+            "Expected a non-negative int but got {} (argument {} of '{}')",
+            num,
+            position + 1,
+            name
+        )));
+    }
+    Ok(num as usize)
+}
+
+fn invalid(message: String) -> Error {
+    Error::RuntimeError {
+        token: None,
+        code: ErrorCode::InvalidArgument,
+        message,
+        trace: Vec::new(),
+    }
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+// xorshift64*: small, fast and plenty for scripts; not for cryptography
+fn seed_state(seed: u64) -> u64 {
+    const MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+    // a zero state would only ever produce zeros
+    match seed ^ MIX {
+        0 => MIX,
+        state => state,
+    }
+}
+
+fn next_random(state: &Cell<u64>) -> f64 {
+    let mut x = state.get();
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    state.set(x);
+    // the top 53 bits fill an f64 mantissa exactly
+    (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Backend;
+    use crate::test_support::{interpreter_on, on_each_backend};
+
+    fn eval(backend: Backend, source: &str) -> String {
+        interpreter_on(backend)
+            .eval_expression(source)
+            .unwrap()
+            .to_string()
+    }
+
+    fn eval_err(backend: Backend, source: &str) -> (ErrorCode, String) {
+        match interpreter_on(backend).eval_expression(source) {
+            Err(Error::RuntimeError { code, message, .. }) => (code, message),
+            other => panic!("expected a runtime error for {}: {:?}", source, other),
+        }
+    }
+
+    on_each_backend!(math, |backend| {
+        assert_eq!(eval(backend, "sqrt(16)"), "4");
+        assert_eq!(eval(backend, "floor(2.7)"), "2");
+        assert_eq!(eval(backend, "abs(-3)"), "3");
+        assert_eq!(eval(backend, "pow(2, 10)"), "1024");
+        assert_eq!(eval(backend, "min(2, 3) + max(2, 3)"), "5");
+        assert_eq!(eval(backend, "clock() > 0"), "true");
+
+        let interpreter = interpreter_on(backend);
+        let draw = |source| interpreter.eval_expression(source).unwrap().as_number();
+        draw("random_seed(42)");
+        let first = draw("random()").unwrap();
+        draw("random_seed(42)");
+        assert_eq!(draw("random()"), Some(first));
+        assert!((0.0..1.0).contains(&first));
+    });
+
+    on_each_backend!(strings, |backend| {
+        assert_eq!(eval(backend, "len(\"hello\")"), "5");
+        assert_eq!(eval(backend, "substring(\"hello\", 1, 3)"), "el");
+        assert_eq!(eval(backend, "index_of(\"hello\", \"l\")"), "2");
+        assert_eq!(eval(backend, "index_of(\"hello\", \"z\")"), "-1");
+        assert_eq!(eval(backend, "upper(\"abc\") + lower(\"DEF\")"), "ABCdef");
+        assert_eq!(eval(backend, "split(\"a,b,,c\", \",\")"), "[a, b, , c]");
+        assert_eq!(eval(backend, "trim(\"  x \")"), "x");
+    });
+
+    on_each_backend!(types_and_conversions, |backend| {
+        assert_eq!(eval(backend, "type_of(1)"), "int");
+        assert_eq!(eval(backend, "type_of(1.5)"), "float");
+        assert_eq!(eval(backend, "type_of(\"a\")"), "string");
+        assert_eq!(eval(backend, "type_of(nil)"), "nil");
+        assert_eq!(eval(backend, "type_of(clock)"), "function");
+        assert_eq!(eval(backend, "to_number(\" 2.5 \") + 1"), "3.5");
+        assert_eq!(
+            eval(backend, "type_of(to_number(\"9007199254740993\"))"),
+            "int"
+        );
+        assert_eq!(eval(backend, "to_number(\"abc\")"), "Nil");
+        assert_eq!(eval(backend, "to_string(12) + \"!\""), "12!");
+    });
+
+    on_each_backend!(lists, |backend| {
+        let interpreter = interpreter_on(backend);
+        interpreter.set_global("xs", vec![1.0, 2.0]);
+        let eval = |source| interpreter.eval_expression(source).unwrap().to_string();
+
+        assert_eq!(eval("push(xs, 3)"), "Nil");
+        assert_eq!(eval("insert(xs, 0, 0)"), "Nil");
+        assert_eq!(eval("insert(xs, 4, 4)"), "Nil");
+        assert_eq!(eval("xs"), "[0, 1, 2, 3, 4]");
+        assert_eq!(eval("pop(xs)"), "4");
+        assert_eq!(eval("remove(xs, 1)"), "1");
+        assert_eq!(eval("slice(xs, 1, 3)"), "[2, 3]");
+        assert_eq!(eval("length(xs) + len(\"ab\")"), "5");
+
+        assert_eq!(eval_err(backend, "pop([])").0, ErrorCode::IndexOutOfBounds);
+        assert_eq!(
+            eval_err(backend, "remove([1], 1)").0,
+            ErrorCode::IndexOutOfBounds
+        );
+        assert_eq!(
+            eval_err(backend, "insert([1], 2, 0)").0,
+            ErrorCode::IndexOutOfBounds
+        );
+        assert_eq!(
+            eval_err(backend, "slice([1], 0, 2)").0,
+            ErrorCode::IndexOutOfBounds
+        );
+        assert_eq!(
+            eval_err(backend, "push(\"a\", 1)").0,
+            ErrorCode::TypeMismatch
+        );
+        assert_eq!(eval_err(backend, "length(1)").0, ErrorCode::TypeMismatch);
+        // strings have `len`
+        assert_eq!(
+            eval_err(backend, "length(\"ab\")").0,
+            ErrorCode::TypeMismatch
+        );
+    });
+
+    on_each_backend!(maps, |backend| {
+        let interpreter = interpreter_on(backend);
+        let map = interpreter.eval_expression("{\"b\": 1, \"a\": 2, 3: 3}");
+        interpreter.set_global("m", map.unwrap());
+        let eval = |source| interpreter.eval_expression(source).unwrap().to_string();
+
+        assert_eq!(eval("has(m, \"a\")"), "true");
+        assert_eq!(eval("has(m, \"z\")"), "false");
+        assert_eq!(eval("keys(m)"), "[b, a, 3]");
+        assert_eq!(eval("values(m)"), "[1, 2, 3]");
+        assert_eq!(eval("remove(m, \"b\")"), "1");
+        assert_eq!(eval("keys(m)"), "[a, 3]");
+        assert_eq!(eval("length(m)"), "2");
+
+        assert_eq!(
+            eval_err(backend, "remove({}, \"b\")").0,
+            ErrorCode::KeyNotFound
+        );
+        assert_eq!(eval_err(backend, "has([], 1)").0, ErrorCode::TypeMismatch);
+        assert_eq!(eval_err(backend, "has({}, nil)").0, ErrorCode::TypeMismatch);
+    });
+
+    on_each_backend!(io_is_opt_in, |backend| {
+        assert_eq!(
+            eval_err(backend, "file_exists(\"Cargo.toml\")"),
+            (
+                ErrorCode::CapabilityDisabled,
+                String::from("'file_exists' needs I/O, which is disabled.")
+            )
+        );
+
+        let interpreter = interpreter_on(backend);
+        interpreter.enable_io();
+        // both backends run at once, so each needs a file of its own
+        let name = format!("lox-io-{}-{:?}.txt", std::process::id(), backend);
+        let path = std::env::temp_dir().join(name);
+        interpreter.set_global("path", path.to_str().unwrap());
+        let eval = |source| interpreter.eval_expression(source).unwrap().to_string();
+
+        assert_eq!(eval("file_exists(path)"), "false");
+        assert_eq!(eval("write_file(path, \"a\nb\")"), "Nil");
+        assert_eq!(eval("file_exists(path)"), "true");
+        assert_eq!(eval("split(read_file(path), \"\n\")"), "[a, b]");
+        fs::remove_file(&path).unwrap();
+
+        match interpreter.eval_expression("read_file(path)") {
+            Err(Error::RuntimeError { code, .. }) => assert_eq!(code, ErrorCode::IoFailed),
+            _ => panic!("expected an I/O error"),
+        }
+    });
+
+    on_each_backend!(reports_arity_and_type_errors, |backend| {
+        assert_eq!(eval_err(backend, "sqrt()").0, ErrorCode::ArityMismatch);
+        assert_eq!(
+            eval_err(backend, "sqrt(\"4\")"),
+            (
+                ErrorCode::TypeMismatch,
+                String::from("Expected number but got string (argument 1 of 'sqrt')")
+            )
+        );
+        assert_eq!(
+            eval_err(backend, "substring(\"abc\", 2, 5)").0,
+            ErrorCode::InvalidArgument
+        );
+        assert_eq!(
+            eval_err(backend, "substring(\"abc\", 0.5, 1)").0,
+            ErrorCode::TypeMismatch
+        );
+        assert_eq!(
+            eval_err(backend, "to_number(true)").0,
+            ErrorCode::InvalidArgument
+        );
+        assert_eq!(
+            eval_err(backend, "split(\"abc\", \"\")").0,
+            ErrorCode::InvalidArgument
+        );
+    });
+}
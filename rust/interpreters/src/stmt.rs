@@ -1,88 +1,253 @@
 use crate::environment::Environment;
-use crate::error::Error;
+use crate::error::{Error, ErrorCode};
 use crate::expr::Expr;
-use crate::interpreter::{evaluate, Interpreter};
-use crate::token::Token;
+use crate::interner::{intern, Symbol};
+use crate::interpreter::{evaluate, is_truthy, Interpreter};
+use crate::lox_class::LoxClass;
+use crate::lox_function::{FunctionKind, LoxFunction, LoxMethod};
+use crate::token::{Span, Token, TokenType};
 use crate::typer::Typer;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 pub enum Stmt {
-    Block(Box<Vec<Stmt>>), // statements
-    Expression(Box<Expr>), // expr
-    Print(Box<Expr>),
+    Block(Vec<Stmt>, Span), // statements, span including braces
+    Class(Box<Token>, Option<Expr>, Vec<Stmt>), // name, superclass, methods
+    Expression(Box<Expr>),  // expr
+    Function(Box<Token>, Rc<Vec<Token>>, Rc<Vec<Stmt>>), // name, params, body
+    If(Box<Expr>, Box<Stmt>, Option<Box<Stmt>>, Span), // condition, then_branch, else_branch, keyword
+    Print(Box<Expr>, Span),                            // expr, keyword
+    Return(Box<Token>, Option<Expr>),                  // keyword, value
     Var(Box<Token>, Option<Expr>),
+    While(Box<Expr>, Box<Stmt>, Span), // condition, body, keyword
+}
+
+fn span_of(statements: &[Stmt]) -> Option<Span> {
+    match (statements.first(), statements.last()) {
+        (Some(first), Some(last)) => Some(first.span().to(last.span())),
+        _ => None,
+    }
 }
 
 impl Stmt {
-    pub fn visit(self, interpreter: &Interpreter) -> Result<(), Error> {
+    /// Source range covered by the whole statement.
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Block(_statements, span) => *span,
+            Stmt::Class(name, _superclass, methods) => match span_of(methods) {
+                Some(methods) => name.span.to(methods),
+                None => name.span,
+            },
+            Stmt::Expression(expr) => expr.span(),
+            Stmt::Function(name, _params, body) => match span_of(body) {
+                Some(body) => name.span.to(body),
+                None => name.span,
+            },
+            Stmt::If(_condition, then_branch, else_branch, keyword) => match else_branch {
+                Some(else_branch) => keyword.to(else_branch.span()),
+                None => keyword.to(then_branch.span()),
+            },
+            Stmt::Print(expr, keyword) => keyword.to(expr.span()),
+            Stmt::Return(keyword, value) => match value {
+                Some(value) => keyword.span.to(value.span()),
+                None => keyword.span,
+            },
+            Stmt::Var(name, initializer) => match initializer {
+                Some(initializer) => name.span.to(initializer.span()),
+                None => name.span,
+            },
+            Stmt::While(_condition, body, keyword) => keyword.to(body.span()),
+        }
+    }
+
+    pub fn visit(&self, interpreter: &Interpreter) -> Result<(), Error> {
         match self {
-            Stmt::Block(statements) => self.visitBlockStmt(*statements, interpreter),
-            Stmt::Expression(expr) => self.visitExpressionStmt(*expr),
-            Stmt::Print(expr) => self.visitPrintStmt(*expr),
-            Stmt::Var(token, expr) => self.visitVarStmt(*token, expr, interpreter),
+            Stmt::Block(statements, _span) => self.visit_block_stmt(statements, interpreter),
+            Stmt::Class(name, superclass, methods) => {
+                self.visit_class_stmt(name, superclass, methods, interpreter)
+            }
+            Stmt::Expression(expr) => self.visit_expression_stmt(expr, interpreter),
+            Stmt::Function(name, params, body) => {
+                self.visit_function_stmt(name, params, body, interpreter)
+            }
+            Stmt::If(condition, then_branch, else_branch, _keyword) => {
+                self.visit_if_stmt(condition, then_branch, else_branch, interpreter)
+            }
+            Stmt::Print(expr, keyword) => self.visit_print_stmt(expr, keyword, interpreter),
+            Stmt::Return(_keyword, value) => self.visit_return_stmt(value, interpreter),
+            Stmt::Var(token, expr) => self.visit_var_stmt(token, expr, interpreter),
+            Stmt::While(condition, body, _keyword) => {
+                self.visit_while_stmt(condition, body, interpreter)
+            }
         }
     }
 
-    fn execute_block(
+    fn visit_block_stmt(
+        &self,
+        statements: &[Stmt],
+        interpreter: &Interpreter,
+    ) -> Result<(), Error> {
+        interpreter.execute_block(statements, Environment::new(interpreter.get_environment()))
+    }
+
+    fn visit_class_stmt(
         &self,
-        statements: Vec<Self>,
-        environment: Environment,
+        name: &Token,
+        superclass: &Option<Expr>,
+        methods: &[Stmt],
         interpreter: &Interpreter,
     ) -> Result<(), Error> {
-        let previous_env = interpreter.get_environment();
-        interpreter.set_environment(environment);
-        for statement in statements {
-            match interpreter.execute(statement) {
-                Ok(()) => {}
-                Err(err) => {
-                    interpreter.set_environment(previous_env);
-                    return Err(err);
+        let superclass = match superclass {
+            Some(superclass) => match evaluate(superclass, interpreter)? {
+                Typer::Class(class) => Some(class),
+                _ => {
+                    return Err(Error::RuntimeError {
+                        token: Some(name.clone()),
+                        code: ErrorCode::SuperclassNotClass,
+                        message: String::from("Superclass must be a class."),
+                        trace: Vec::new(),
+                    })
                 }
-            };
+            },
+            None => None,
+        };
+
+        interpreter
+            .get_environment()
+            .borrow_mut()
+            .define(name.lexeme.clone(), None);
+
+        let mut closure = interpreter.get_environment();
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::new(closure);
+            environment.define(intern("super"), Some(Typer::Class(Rc::clone(superclass))));
+            closure = interpreter.alloc(RefCell::new(environment));
+        }
+
+        let mut functions: HashMap<Symbol, Rc<dyn LoxMethod>> = HashMap::new();
+        for method in methods {
+            if let Stmt::Function(method_name, params, body) = method {
+                let kind = if method_name.lexeme == "init" {
+                    FunctionKind::Initializer
+                } else {
+                    FunctionKind::Method
+                };
+                let function = LoxFunction::new(
+                    (**method_name).clone(),
+                    Rc::clone(params),
+                    Rc::clone(body),
+                    Rc::clone(&closure),
+                    kind,
+                );
+                functions.insert(method_name.lexeme.clone(), interpreter.alloc(function));
+            }
         }
-        interpreter.set_environment(previous_env);
+
+        let class = LoxClass::new(name.lexeme.to_string(), superclass, functions);
+        interpreter
+            .get_environment()
+            .borrow_mut()
+            .assign(name, Typer::Class(interpreter.alloc(class)))
+    }
+
+    fn visit_expression_stmt(&self, expr: &Expr, interpreter: &Interpreter) -> Result<(), Error> {
+        evaluate(expr, interpreter)?;
         Ok(())
     }
 
-    fn visitBlockStmt(
+    fn visit_function_stmt(
         &self,
-        statements: Vec<Stmt>,
+        name: &Token,
+        params: &Rc<Vec<Token>>,
+        body: &Rc<Vec<Stmt>>,
         interpreter: &Interpreter,
     ) -> Result<(), Error> {
-        self.execute_block(
-            statements,
-            Environment::new(interpreter.environment.borrow()),
-            interpreter,
+        let function = LoxFunction::new(
+            name.clone(),
+            Rc::clone(params),
+            Rc::clone(body),
+            interpreter.get_environment(),
+            FunctionKind::Function,
+        );
+        interpreter.get_environment().borrow_mut().define(
+            name.lexeme.clone(),
+            Some(Typer::Callable(interpreter.alloc(function))),
         );
         Ok(())
     }
 
-    fn visitExpressionStmt(&self, expr: Expr) -> Result<(), Error> {
-        evaluate(expr)?;
+    fn visit_if_stmt(
+        &self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: &Option<Box<Stmt>>,
+        interpreter: &Interpreter,
+    ) -> Result<(), Error> {
+        if is_truthy(&evaluate(condition, interpreter)?) {
+            interpreter.execute(then_branch)?;
+        } else if let Some(else_branch) = else_branch {
+            interpreter.execute(else_branch)?;
+        }
         Ok(())
     }
 
-    fn visitPrintStmt(&self, expr: Expr) -> Result<(), Error> {
-        let value = evaluate(expr)?;
-        println!("{}", value);
-        Ok(())
+    fn visit_print_stmt(
+        &self,
+        expr: &Expr,
+        keyword: &Span,
+        interpreter: &Interpreter,
+    ) -> Result<(), Error> {
+        let value = evaluate(expr, interpreter)?;
+        interpreter
+            .write_line(&value.to_string())
+            .map_err(|err| Error::RuntimeError {
+                token: Some(Token::with_span(TokenType::PRINT, "print", None, *keyword)),
+                code: ErrorCode::OutputFailed,
+                message: format!("Could not write output: {}", err),
+                trace: Vec::new(),
+            })
+    }
+
+    fn visit_return_stmt(
+        &self,
+        value: &Option<Expr>,
+        interpreter: &Interpreter,
+    ) -> Result<(), Error> {
+        let value = match value {
+            Some(value) => evaluate(value, interpreter)?,
+            None => Typer::Nil,
+        };
+        Err(Error::Return { value })
     }
 
-    fn visitVarStmt(
+    fn visit_var_stmt(
         &self,
-        name: Token,
-        initializer: Option<Expr>,
+        name: &Token,
+        initializer: &Option<Expr>,
         interpreter: &Interpreter,
     ) -> Result<(), Error> {
         let mut value: Option<Typer> = None;
         if let Some(initializer) = initializer {
-            value = Some(evaluate(initializer)?);
+            value = Some(evaluate(initializer, interpreter)?);
         }
-        let mut value = &value;
         interpreter
-            .environment
+            .get_environment()
             .borrow_mut()
-            .define(name.lexeme.clone(), *value);
+            .define(name.lexeme.clone(), value);
 
         Ok(())
     }
+
+    fn visit_while_stmt(
+        &self,
+        condition: &Expr,
+        body: &Stmt,
+        interpreter: &Interpreter,
+    ) -> Result<(), Error> {
+        while is_truthy(&evaluate(condition, interpreter)?) {
+            interpreter.execute(body)?;
+        }
+        Ok(())
+    }
 }
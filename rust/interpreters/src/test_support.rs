@@ -0,0 +1,88 @@
+//! Fixtures shared by the unit tests of every module.
+
+use crate::interpreter::{Backend, Interpreter};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Scans `source`, which must be free of lexical errors.
+pub(crate) fn scan(source: &str) -> Vec<Token> {
+    let (tokens, errors) = Scanner::new(String::from(source)).scan_tokens();
+    assert!(errors.is_empty());
+    tokens
+}
+
+/// Scans, parses and resolves `source`, which must be a valid script.
+pub(crate) fn parse(source: &str) -> Vec<Stmt> {
+    let tokens = scan(source);
+    let (statements, errors) = Parser::new(tokens).parse();
+    assert!(errors.is_empty());
+    assert!(Resolver::new().resolve(&statements).is_empty());
+    statements
+}
+
+/// A fresh interpreter running on `backend`.
+pub(crate) fn interpreter_on(backend: Backend) -> Interpreter {
+    let interpreter = Interpreter::new();
+    interpreter.set_backend(backend);
+    interpreter
+}
+
+/// Runs `source`, which must run without errors, on `interpreter`.
+pub(crate) fn run(interpreter: &Interpreter, source: &str) {
+    interpreter.interpret(parse(source)).unwrap();
+}
+
+/// The global `name`, which must be defined, as scripts would print it.
+pub(crate) fn global(interpreter: &Interpreter, name: &str) -> String {
+    interpreter.get_global(name).unwrap().to_string()
+}
+
+/// Declares a test that must pass on every backend, as a module with
+/// one test per backend.
+macro_rules! on_each_backend {
+    ($name:ident, |$backend:ident| $body:block) => {
+        mod $name {
+            use super::*;
+
+            fn body($backend: $crate::interpreter::Backend) $body
+
+            #[test]
+            fn tree_walk() {
+                body($crate::interpreter::Backend::TreeWalk)
+            }
+
+            #[test]
+            fn bytecode() {
+                body($crate::interpreter::Backend::Bytecode)
+            }
+        }
+    };
+}
+
+pub(crate) use on_each_backend;
+
+/// A writer tests can keep a handle to after giving it away.
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    pub(crate) fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
@@ -1,4 +1,8 @@
+use crate::interner::{intern, Symbol};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::BigDecimal;
 use std::fmt;
+use std::rc::Rc;
 
 #[macro_export]
 macro_rules! enum_str {
@@ -19,6 +23,13 @@ macro_rules! enum_str {
                     $($name::$variant => stringify!($variant)),*
                 }
             }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $(stringify!($variant) => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
         }
     };
 }
@@ -30,6 +41,8 @@ enum_str! {
         RIGHT_PAREN,
         LEFT_BRACE,
         RIGHT_BRACE,
+        LEFT_BRACKET,
+        RIGHT_BRACKET,
         COMMA,
         DOT,
         MINUS,
@@ -38,6 +51,7 @@ enum_str! {
         COLON,
         SLASH,
         STAR,
+        PERCENT,
         QUESTION,
         TERNARY,
 
@@ -50,6 +64,7 @@ enum_str! {
         GREATER_EQUAL,
         LESS,
         LESS_EQUAL,
+        TILDE_SLASH,
 
         // ------ LITERALS ----------
         IDENTIFIER,
@@ -111,61 +126,114 @@ impl PartialEq for TokenType {
 
 #[derive(Clone, Debug)]
 pub enum Literal {
+    Int(i64),
     Number(f64),
-    Str(String),
+    BigInt(Rc<BigInt>),
+    Decimal(Rc<BigDecimal>),
+    Str(Symbol),
     Bool(bool),
     Nil,
 }
 
-impl Literal {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Literal::Bool(b) => b.to_string(),
-            Literal::Nil => String::from("Nil"),
-            Literal::Number(num) => num.to_string(),
-            Literal::Str(st) => st.clone(),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Int(num) => write!(f, "{}", num),
+            Literal::BigInt(num) => write!(f, "{}", num),
+            Literal::Decimal(num) => write!(f, "{}", num.normalized().to_plain_string()),
+            Literal::Nil => write!(f, "Nil"),
+            Literal::Number(num) => write!(f, "{}", num),
+            Literal::Str(st) => write!(f, "{}", st),
         }
     }
 }
 
-impl fmt::Display for Literal {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let s = self.to_string();
-        write!(f, "{}", s)
+/// Location of a piece of source text. `start` and `end` are byte offsets
+/// (end exclusive); `line` and `column` are 1-based and point at `start`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        Span {
+            end: first.end.max(last.end),
+            ..first
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Symbol,
     pub literal: Option<Literal>,
     pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(token_type: TokenType, lexeme: &str, literal: Option<Literal>, line: usize) -> Self {
         Token {
             token_type,
-            lexeme: String::from(lexeme),
+            lexeme: intern(lexeme),
             literal,
             line,
+            span: Span {
+                line,
+                ..Span::default()
+            },
+        }
+    }
+
+    pub fn with_span(
+        token_type: TokenType,
+        lexeme: &str,
+        literal: Option<Literal>,
+        span: Span,
+    ) -> Self {
+        Token {
+            span,
+            ..Token::new(token_type, lexeme, literal, span.line)
         }
     }
 
     pub fn new_ternary(line: usize) -> Self {
         Token::new(TokenType::TERNARY, "?..:", None, line)
     }
+}
 
-    pub fn to_string(&self) -> String {
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let literal_str = match &self.literal {
             Some(literal) => literal.to_string(),
             None => "LitNone".to_string(),
         };
 
-        format!(
+        write!(
+            f,
             "({} | {} | {})",
-            &self.token_type.to_string(),
+            self.token_type.to_string(),
             &self.lexeme,
             literal_str
         )
@@ -179,10 +247,25 @@ mod test {
     #[test]
     fn display_test() {
         assert_eq!(TokenType::NUMBER.to_string(), "NUMBER");
+        assert_eq!(
+            TokenType::from_name("LESS_EQUAL"),
+            Some(TokenType::LESS_EQUAL)
+        );
+        assert_eq!(TokenType::from_name("less_equal"), None);
 
         assert_eq!(Literal::Number(4.0).to_string(), "4");
+        assert_eq!(Literal::Int(-7).to_string(), "-7");
         assert_eq!(Literal::Bool(true).to_string(), "true");
-        assert_eq!(Literal::Str("Hello".to_string()).to_string(), "Hello");
+        assert_eq!(Literal::Str(intern("Hello")).to_string(), "Hello");
         assert_eq!(Literal::Nil.to_string(), "Nil");
     }
+
+    #[test]
+    fn span_to() {
+        let first = Span::new(0, 3, 1, 1);
+        let second = Span::new(8, 10, 2, 4);
+
+        assert_eq!(first.to(second), Span::new(0, 10, 1, 1));
+        assert_eq!(second.to(first), Span::new(0, 10, 1, 1));
+    }
 }
@@ -1,31 +1,396 @@
+use crate::error::{Error, ErrorCode};
+use crate::gc::{address, Address};
+use crate::interner::{intern, Symbol};
+use crate::lox_class::{LoxClass, LoxInstance};
+use crate::lox_function::LoxCallable;
+use crate::lox_map::LoxMap;
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, ToPrimitive};
+use std::cell::RefCell;
 use std::fmt::{self, Display};
+use std::rc::Rc;
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Typer {
+    Int(i64),
     Number(f64),
-    Str(String),
+    BigInt(Rc<BigInt>),
+    Decimal(Rc<BigDecimal>),
+    Str(Symbol),
     Boolean(bool),
+    Callable(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<Vec<Typer>>>),
+    Map(Rc<RefCell<LoxMap>>),
     Nil,
 }
 
+/// The name embedders see for script values.
+pub type Value = Typer;
+
+/// How number literals become values. `Float` gives i64 ints and f64
+/// floats; `Exact` gives ints of any size and decimals that adding,
+/// subtracting and multiplying never round, for scripts doing money or
+/// other math where that matters. Division is exact too unless the
+/// quotient needs more than `DIVISION_DIGITS` significant digits, as `1 / 3`
+/// does; it is then rounded to that many.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumberMode {
+    #[default]
+    Float,
+    Exact,
+}
+
+/// How many significant digits an exact division keeps when the quotient
+/// doesn't fit in fewer.
+pub const DIVISION_DIGITS: u64 = 100;
+
 impl Typer {
-    pub fn to_string(&self) -> String {
+    /// Any kind of number as a float; large ints and long decimals may
+    /// lose precision.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Typer::Int(num) => Some(*num as f64),
+            Typer::Number(num) => Some(*num),
+            Typer::BigInt(num) => num.to_f64(),
+            Typer::Decimal(num) => num.to_f64(),
+            _ => None,
+        }
+    }
+
+    /// An int of either size, as long as it fits in an i64.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Typer::Int(num) => Some(*num),
+            Typer::BigInt(num) => num.to_i64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_decimal(&self) -> Option<&BigDecimal> {
+        match self {
+            Typer::Decimal(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Typer::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Typer::Str(st) => Some(st),
+            _ => None,
+        }
+    }
+
+    /// A copy of the list's items; later changes to the list don't show up.
+    pub fn as_list(&self) -> Option<Vec<Typer>> {
         match self {
-            Typer::Boolean(b) => b.to_string(),
-            Typer::Nil => String::from("Nil"),
-            Typer::Number(num) => num.to_string(),
-            Typer::Str(st) => st.clone(),
+            Typer::List(items) => Some(items.borrow().clone()),
+            _ => None,
         }
     }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Typer::Nil)
+    }
+
+    /// `false` and `nil` are falsey, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Typer::Boolean(false) | Typer::Nil)
+    }
+
+    /// Name of the value's type as scripts and error messages spell it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Typer::Int(_) | Typer::BigInt(_) => "int",
+            Typer::Decimal(_) => "decimal",
+            Typer::Number(_) => "float",
+            Typer::Str(_) => "string",
+            Typer::Boolean(_) => "boolean",
+            Typer::Callable(_) => "function",
+            Typer::Class(_) => "class",
+            Typer::Instance(_) => "instance",
+            Typer::List(_) => "list",
+            Typer::Map(_) => "map",
+            Typer::Nil => "nil",
+        }
+    }
+}
+
+thread_local! {
+    // the lists and maps being printed, innermost last
+    static PRINTING: RefCell<Vec<Address>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Writes a list or map with `write`, or `placeholder` if it is already
+/// being printed further out, so one that contains itself doesn't print
+/// forever.
+fn print_once(
+    at: Address,
+    f: &mut fmt::Formatter<'_>,
+    placeholder: &str,
+    write: impl FnOnce(&mut fmt::Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    if PRINTING.with(|printing| printing.borrow().contains(&at)) {
+        return write!(f, "{}", placeholder);
+    }
+    PRINTING.with(|printing| printing.borrow_mut().push(at));
+    let result = write(f);
+    PRINTING.with(|printing| printing.borrow_mut().pop());
+    result
 }
 
 impl Display for Typer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Typer::Boolean(b) => write!(f, "{}", b),
+            Typer::Callable(callable) => write!(f, "<fn {}>", callable.name()),
+            Typer::Class(class) => write!(f, "{}", class.name),
+            Typer::Instance(instance) => write!(f, "{} instance", instance.borrow().class_name()),
+            Typer::List(items) => print_once(address(items), f, "[...]", |f| {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }),
+            Typer::Map(map) => {
+                print_once(address(map), f, "{...}", |f| write!(f, "{}", map.borrow()))
+            }
             Typer::Nil => write!(f, "Nil"),
+            Typer::Int(num) => write!(f, "{}", num),
+            Typer::BigInt(num) => write!(f, "{}", num),
+            // trailing zeros dropped and never in exponent form: `1.50` is `1.5`
+            Typer::Decimal(num) => write!(f, "{}", num.normalized().to_plain_string()),
             Typer::Number(num) => write!(f, "{}", num),
             Typer::Str(st) => write!(f, "{}", st),
         }
     }
 }
+
+impl From<f64> for Typer {
+    fn from(value: f64) -> Self {
+        Typer::Number(value)
+    }
+}
+
+impl From<i64> for Typer {
+    fn from(value: i64) -> Self {
+        Typer::Int(value)
+    }
+}
+
+impl From<BigInt> for Typer {
+    fn from(value: BigInt) -> Self {
+        Typer::BigInt(Rc::new(value))
+    }
+}
+
+impl From<BigDecimal> for Typer {
+    fn from(value: BigDecimal) -> Self {
+        Typer::Decimal(Rc::new(value))
+    }
+}
+
+impl From<bool> for Typer {
+    fn from(value: bool) -> Self {
+        Typer::Boolean(value)
+    }
+}
+
+impl From<String> for Typer {
+    fn from(value: String) -> Self {
+        Typer::Str(intern(&value))
+    }
+}
+
+impl From<&str> for Typer {
+    fn from(value: &str) -> Self {
+        Typer::Str(intern(value))
+    }
+}
+
+impl From<()> for Typer {
+    fn from(_: ()) -> Self {
+        Typer::Nil
+    }
+}
+
+impl<T: Into<Typer>> From<Option<T>> for Typer {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Typer::Nil, Into::into)
+    }
+}
+
+impl<T: Into<Typer>> From<Vec<T>> for Typer {
+    fn from(values: Vec<T>) -> Self {
+        Typer::List(Rc::new(RefCell::new(
+            values.into_iter().map(Into::into).collect(),
+        )))
+    }
+}
+
+/// The way back from script values to Rust ones, for native functions
+/// reading their arguments. Fails with a runtime error naming both types.
+pub trait FromTyper: Sized {
+    fn from_typer(value: Typer) -> Result<Self, Error>;
+}
+
+fn mismatch(expected: &str, value: &Typer) -> Error {
+    Error::RuntimeError {
+        token: None,
+        code: ErrorCode::TypeMismatch,
+        message: format!("Expected {} but got {}.", expected, value.type_name()),
+        trace: Vec::new(),
+    }
+}
+
+impl FromTyper for Typer {
+    fn from_typer(value: Typer) -> Result<Self, Error> {
+        Ok(value)
+    }
+}
+
+impl FromTyper for f64 {
+    fn from_typer(value: Typer) -> Result<Self, Error> {
+        match value {
+            Typer::Int(num) => Ok(num as f64),
+            Typer::Number(num) => Ok(num),
+            Typer::BigInt(_) | Typer::Decimal(_) => {
+                value.as_number().ok_or_else(|| mismatch("number", &value))
+            }
+            _ => Err(mismatch("number", &value)),
+        }
+    }
+}
+
+/// Only accepts ints; floats are rejected rather than truncated.
+impl FromTyper for i64 {
+    fn from_typer(value: Typer) -> Result<Self, Error> {
+        match value {
+            Typer::Int(num) => Ok(num),
+            Typer::BigInt(ref num) => num.to_i64().ok_or_else(|| Error::RuntimeError {
+                token: None,
+                code: ErrorCode::IntegerOverflow,
+                message: format!("Expected an int that fits in 64 bits but got {}.", num),
+                trace: Vec::new(),
+            }),
+            _ => Err(mismatch("int", &value)),
+        }
+    }
+}
+
+impl FromTyper for bool {
+    fn from_typer(value: Typer) -> Result<Self, Error> {
+        match value {
+            Typer::Boolean(b) => Ok(b),
+            _ => Err(mismatch("boolean", &value)),
+        }
+    }
+}
+
+impl FromTyper for String {
+    fn from_typer(value: Typer) -> Result<Self, Error> {
+        match value {
+            Typer::Str(st) => Ok(st.to_string()),
+            _ => Err(mismatch("string", &value)),
+        }
+    }
+}
+
+impl<T: FromTyper> FromTyper for Option<T> {
+    fn from_typer(value: Typer) -> Result<Self, Error> {
+        match value {
+            Typer::Nil => Ok(None),
+            value => T::from_typer(value).map(Some),
+        }
+    }
+}
+
+impl<T: FromTyper> FromTyper for Vec<T> {
+    fn from_typer(value: Typer) -> Result<Self, Error> {
+        match value {
+            Typer::List(items) => items.borrow().iter().cloned().map(T::from_typer).collect(),
+            _ => Err(mismatch("list", &value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors() {
+        assert_eq!(Value::from(2.0).as_number(), Some(2.0));
+        assert_eq!(Value::from(3).as_number(), Some(3.0));
+        assert_eq!(Value::from(3).as_int(), Some(3));
+        assert_eq!(Value::from(3.0).as_int(), None);
+        assert_eq!(Value::from("x").as_number(), None);
+        assert_eq!(Value::from("x").as_str(), Some("x"));
+        assert_eq!(Value::from(false).as_bool(), Some(false));
+        assert_eq!(Value::from(vec![1.0]).as_list().unwrap().len(), 1);
+        assert!(Value::Nil.is_nil());
+        assert!(!Value::Nil.is_truthy());
+        assert!(Value::from(0.0).is_truthy());
+    }
+
+    #[test]
+    fn lists_that_contain_themselves_print() {
+        let items = Rc::new(RefCell::new(vec![Typer::Int(1)]));
+        let list = Typer::List(Rc::clone(&items));
+        items.borrow_mut().push(list.clone());
+        assert_eq!(list.to_string(), "[1, [...]]");
+
+        // the same list twice side by side is not a cycle
+        let inner = Typer::List(Rc::new(RefCell::new(vec![Typer::Int(2)])));
+        let pair = Typer::List(Rc::new(RefCell::new(vec![inner.clone(), inner])));
+        assert_eq!(pair.to_string(), "[[2], [2]]");
+        items.borrow_mut().clear();
+    }
+
+    #[test]
+    fn converts_rust_values() {
+        assert_eq!(Typer::from(1.5).to_string(), "1.5");
+        let decimal = |text: &str| Typer::from(text.parse::<BigDecimal>().unwrap());
+        assert_eq!(decimal("1.50").to_string(), "1.5");
+        assert_eq!(decimal("2.000").to_string(), "2");
+        assert_eq!(decimal("1e-10").to_string(), "0.0000000001");
+        assert_eq!(decimal("12e3").to_string(), "12000");
+        assert_eq!(Typer::from(Some("a")).to_string(), "a");
+        assert_eq!(Typer::from(None::<bool>).to_string(), "Nil");
+        assert_eq!(Typer::from(vec![1.0, 2.0]).to_string(), "[1, 2]");
+
+        assert_eq!(f64::from_typer(Typer::Number(2.0)).unwrap(), 2.0);
+        assert_eq!(f64::from_typer(Typer::Int(2)).unwrap(), 2.0);
+        assert_eq!(
+            i64::from_typer(Typer::from(9_007_199_254_740_993)).unwrap(),
+            9_007_199_254_740_993
+        );
+        assert!(i64::from_typer(Typer::Number(2.0)).is_err());
+        assert_eq!(i64::from_typer(Typer::from(BigInt::from(5))).unwrap(), 5);
+        assert!(i64::from_typer(Typer::from(BigInt::from(u64::MAX))).is_err());
+        assert_eq!(Option::<String>::from_typer(Typer::Nil).unwrap(), None);
+        assert_eq!(
+            Vec::<bool>::from_typer(Typer::from(vec![true, false])).unwrap(),
+            vec![true, false]
+        );
+        match String::from_typer(Typer::Boolean(true)) {
+            Err(Error::RuntimeError { code, message, .. }) => {
+                assert_eq!(code, ErrorCode::TypeMismatch);
+                assert_eq!(message, "Expected string but got boolean.");
+            }
+            _ => panic!("expected a type mismatch"),
+        }
+    }
+}
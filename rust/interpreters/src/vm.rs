@@ -0,0 +1,765 @@
+use crate::arithmetic;
+use crate::chunk::{Constant, Function, OpCode, Site};
+use crate::error::{Error, ErrorCode};
+use crate::gc::{address, trace_value, Address, Trace};
+use crate::interner::{intern, Symbol};
+use crate::interpreter::{get_index, map_key, set_index, Interpreter};
+use crate::lox_class::{LoxClass, LoxInstance};
+use crate::lox_function::{LoxCallable, LoxMethod};
+use crate::lox_map::LoxMap;
+use crate::token::Token;
+use crate::typer::Typer;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+/// A variable a closure captured. It points at the variable's stack slot
+/// while its scope is live and holds the value itself once the scope ends.
+struct Upvalue(RefCell<UpvalueState>);
+
+enum UpvalueState {
+    Open(usize),
+    Closed(Typer),
+}
+
+/// A compiled function together with the variables it captured.
+#[derive(Clone)]
+pub struct Closure {
+    function: Rc<Function>,
+    upvalues: Rc<Vec<Rc<Upvalue>>>,
+}
+
+impl LoxCallable for Closure {
+    fn arity(&self) -> usize {
+        self.function.arity
+    }
+
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<Typer>) -> Result<Typer, Error> {
+        interpreter
+            .vm
+            .call(interpreter, self.clone(), Typer::Nil, arguments)
+    }
+
+    fn name(&self) -> &str {
+        &self.function.name
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl LoxMethod for Closure {
+    fn bind(
+        &self,
+        interpreter: &Interpreter,
+        instance: Rc<RefCell<LoxInstance>>,
+    ) -> Rc<dyn LoxCallable> {
+        interpreter.alloc(BoundMethod {
+            receiver: instance,
+            method: self.clone(),
+        })
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        visit(address(&self.upvalues));
+        true
+    }
+}
+
+impl Trace for Vec<Rc<Upvalue>> {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        self.iter().for_each(|upvalue| visit(address(upvalue)));
+        true
+    }
+}
+
+impl Trace for Upvalue {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        match self.0.try_borrow() {
+            Ok(state) => {
+                if let UpvalueState::Closed(value) = &*state {
+                    trace_value(value, visit);
+                }
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut state) = self.0.try_borrow_mut() {
+            if let UpvalueState::Closed(value) = &mut *state {
+                *value = Typer::Nil;
+            }
+        }
+    }
+}
+
+/// A method read off an instance; calling it runs the method with the
+/// instance in slot 0 as `this`.
+pub struct BoundMethod {
+    receiver: Rc<RefCell<LoxInstance>>,
+    method: Closure,
+}
+
+impl Trace for BoundMethod {
+    fn trace(&self, visit: &mut dyn FnMut(Address)) -> bool {
+        visit(address(&self.receiver));
+        visit(address(&self.method.upvalues));
+        true
+    }
+}
+
+impl LoxCallable for BoundMethod {
+    fn arity(&self) -> usize {
+        self.method.arity()
+    }
+
+    fn call(&self, interpreter: &Interpreter, arguments: Vec<Typer>) -> Result<Typer, Error> {
+        let receiver = Typer::Instance(Rc::clone(&self.receiver));
+        interpreter
+            .vm
+            .call(interpreter, self.method.clone(), receiver, arguments)
+    }
+
+    fn name(&self) -> &str {
+        self.method.name()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A call in progress: what it runs, how far it got and where its slots
+/// start on the stack.
+struct CallFrame {
+    closure: Closure,
+    ip: usize,
+    base: usize,
+}
+
+impl CallFrame {
+    fn new(closure: Closure, base: usize) -> Self {
+        Self {
+            closure,
+            ip: 0,
+            base,
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let byte = self.closure.function.chunk.code[self.ip];
+        self.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let value = self.closure.function.chunk.read_u16(self.ip);
+        self.ip += 2;
+        value
+    }
+
+    fn constant(&self, index: u16) -> &Constant {
+        &self.closure.function.chunk.constants[index as usize]
+    }
+
+    fn site(&self, index: u16) -> &Site {
+        &self.closure.function.chunk.sites[index as usize]
+    }
+}
+
+fn error(token: &Token, code: ErrorCode, message: impl Into<String>) -> Error {
+    Error::RuntimeError {
+        token: Some(token.clone()),
+        code,
+        message: message.into(),
+        trace: Vec::new(),
+    }
+}
+
+/// Runs compiled functions. Globals, natives, output and the call stack in
+/// traces all come from the `Interpreter`, so scripts see the same world
+/// whichever backend runs them.
+#[derive(Default)]
+pub struct Vm {
+    // one stack for every call in progress, even ones entered from natives,
+    // so open upvalues always point into the right place
+    stack: RefCell<Vec<Typer>>,
+    open_upvalues: RefCell<Vec<Rc<Upvalue>>>,
+}
+
+impl Vm {
+    /// Runs a compiled script and hands back what it returns.
+    pub fn run(&self, interpreter: &Interpreter, script: Rc<Function>) -> Result<Typer, Error> {
+        let closure = Closure {
+            function: script,
+            upvalues: Rc::new(Vec::new()),
+        };
+        self.call(interpreter, closure, Typer::Nil, Vec::new())
+    }
+
+    /// Runs `closure` to completion with `receiver` in slot 0. This is how
+    /// calls from outside the VM get in; calls between compiled functions
+    /// only push a frame.
+    fn call(
+        &self,
+        interpreter: &Interpreter,
+        closure: Closure,
+        receiver: Typer,
+        arguments: Vec<Typer>,
+    ) -> Result<Typer, Error> {
+        let base = self.stack.borrow().len();
+        let depth = interpreter.frame_depth();
+        {
+            let mut stack = self.stack.borrow_mut();
+            stack.push(receiver);
+            stack.extend(arguments);
+        }
+        let result = self
+            .execute(interpreter, CallFrame::new(closure, base))
+            .map_err(|error| interpreter.traced(error));
+        if result.is_err() {
+            self.close_upvalues(base);
+            self.stack.borrow_mut().truncate(base);
+            interpreter.unwind_frames(depth);
+        }
+        result
+    }
+
+    fn execute(&self, interpreter: &Interpreter, mut frame: CallFrame) -> Result<Typer, Error> {
+        let mut callers: Vec<CallFrame> = Vec::new();
+        loop {
+            let op = OpCode::from_byte(frame.read_byte()).expect("chunks hold valid opcodes");
+            match op {
+                OpCode::Constant => {
+                    let index = frame.read_u16();
+                    let value = frame.constant(index).value();
+                    self.push(value.expect("functions are only loaded by closures"));
+                }
+                OpCode::Nil => self.push(Typer::Nil),
+                OpCode::True => self.push(Typer::Boolean(true)),
+                OpCode::False => self.push(Typer::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = frame.base + frame.read_byte() as usize;
+                    let value = self.stack.borrow()[slot].clone();
+                    self.push(value);
+                }
+                OpCode::SetLocal => {
+                    let slot = frame.base + frame.read_byte() as usize;
+                    let value = self.peek(0);
+                    self.stack.borrow_mut()[slot] = value;
+                }
+                OpCode::DefineGlobal => {
+                    let index = frame.read_u16();
+                    let name = match frame.constant(index) {
+                        Constant::Str(name) => name.clone(),
+                        other => panic!("global names are strings, not {:?}", other),
+                    };
+                    let value = self.pop();
+                    interpreter.globals.borrow_mut().define(name, Some(value));
+                }
+                OpCode::GetGlobal => {
+                    let site = frame.read_u16();
+                    let value = interpreter.globals.borrow().get(&frame.site(site).token)?;
+                    self.push(value);
+                }
+                OpCode::SetGlobal => {
+                    let site = frame.read_u16();
+                    interpreter
+                        .globals
+                        .borrow_mut()
+                        .assign(&frame.site(site).token, self.peek(0))?;
+                }
+                OpCode::GetUpvalue => {
+                    let index = frame.read_byte() as usize;
+                    let value = self.read_upvalue(&frame.closure.upvalues[index]);
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = frame.read_byte() as usize;
+                    self.write_upvalue(&frame.closure.upvalues[index], self.peek(0));
+                }
+                OpCode::GetProperty => {
+                    let site = frame.read_u16();
+                    let name = &frame.site(site).token;
+                    let value = match self.pop() {
+                        Typer::Instance(instance) => {
+                            LoxInstance::get(&instance, name, interpreter)?
+                        }
+                        _ => {
+                            return Err(error(
+                                name,
+                                ErrorCode::NotAnInstance,
+                                "Only instances have properties.",
+                            ))
+                        }
+                    };
+                    self.push(value);
+                }
+                OpCode::SetProperty => {
+                    let site = frame.read_u16();
+                    let name = &frame.site(site).token;
+                    let value = self.pop();
+                    match self.pop() {
+                        Typer::Instance(instance) => instance.borrow_mut().set(name, value.clone()),
+                        _ => {
+                            return Err(error(
+                                name,
+                                ErrorCode::NotAnInstance,
+                                "Only instances have fields.",
+                            ))
+                        }
+                    }
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let site = frame.read_u16();
+                    let method = &frame.site(site).token;
+                    let superclass = self.pop();
+                    let this = self.pop();
+                    let found = match superclass {
+                        Typer::Class(superclass) => superclass.find_method(&method.lexeme).cloned(),
+                        _ => None,
+                    };
+                    match (found, this) {
+                        (Some(found), Typer::Instance(instance)) => {
+                            self.push(Typer::Callable(found.bind(interpreter, instance)))
+                        }
+                        _ => {
+                            return Err(error(
+                                method,
+                                ErrorCode::UndefinedProperty,
+                                format!("Undefined property '{}'.", method.lexeme),
+                            ))
+                        }
+                    }
+                }
+                OpCode::GetIndex => {
+                    let site = frame.read_u16();
+                    let bracket = &frame.site(site).token;
+                    let index = self.pop();
+                    let object = self.pop();
+                    self.push(get_index(&object, &index, bracket)?);
+                }
+                OpCode::SetIndex => {
+                    let site = frame.read_u16();
+                    let bracket = &frame.site(site).token;
+                    let value = self.pop();
+                    let index = self.pop();
+                    let object = self.pop();
+                    self.push(set_index(&object, &index, value, bracket)?);
+                }
+                // the site's token is the operator, which tells `binary` what to do
+                OpCode::Equal
+                | OpCode::NotEqual
+                | OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::IntDivide
+                | OpCode::Modulo => {
+                    let site = frame.read_u16();
+                    let operator = &frame.site(site).token;
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.push(arithmetic::binary(operator, left, right)?);
+                }
+                OpCode::Negate => {
+                    let site = frame.read_u16();
+                    let operator = &frame.site(site).token;
+                    let operand = self.pop();
+                    self.push(arithmetic::negate(operator, operand)?);
+                }
+                OpCode::Not => {
+                    let operand = self.pop();
+                    self.push(Typer::Boolean(!operand.is_truthy()));
+                }
+                OpCode::Ternary => {
+                    let site = frame.read_u16();
+                    let operator = &frame.site(site).token;
+                    let second = self.pop();
+                    let first = self.pop();
+                    let value = match self.pop() {
+                        Typer::Boolean(condition) => {
+                            if condition {
+                                first
+                            } else {
+                                second
+                            }
+                        }
+                        Typer::Nil => second,
+                        _ => {
+                            return Err(error(
+                                operator,
+                                ErrorCode::TypeMismatch,
+                                "ternary operation failed.",
+                            ))
+                        }
+                    };
+                    self.push(value);
+                }
+                OpCode::Print => {
+                    let site = frame.read_u16();
+                    let keyword = &frame.site(site).token;
+                    let value = self.pop();
+                    interpreter.write_line(&value.to_string()).map_err(|err| {
+                        error(
+                            keyword,
+                            ErrorCode::OutputFailed,
+                            format!("Could not write output: {}", err),
+                        )
+                    })?;
+                }
+                OpCode::Jump => {
+                    let offset = frame.read_u16() as usize;
+                    frame.ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = frame.read_u16() as usize;
+                    if !self.peek(0).is_truthy() {
+                        frame.ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = frame.read_u16() as usize;
+                    frame.ip -= offset;
+                }
+                OpCode::Call => {
+                    let count = frame.read_byte() as usize;
+                    let site = frame.read_u16();
+                    if let Some(callee) = self.call_value(interpreter, frame.site(site), count)? {
+                        callers.push(mem::replace(&mut frame, callee));
+                    }
+                }
+                OpCode::Closure => {
+                    let index = frame.read_u16();
+                    let function = match frame.constant(index) {
+                        Constant::Function(function) => Rc::clone(function),
+                        other => panic!("closures are made from functions, not {:?}", other),
+                    };
+                    let upvalues: Vec<_> = (0..function.upvalues)
+                        .map(|_| {
+                            let is_local = frame.read_byte() == 1;
+                            let index = frame.read_byte() as usize;
+                            if is_local {
+                                self.capture_upvalue(interpreter, frame.base + index)
+                            } else {
+                                Rc::clone(&frame.closure.upvalues[index])
+                            }
+                        })
+                        .collect();
+                    self.push(Typer::Callable(interpreter.alloc(Closure {
+                        function,
+                        upvalues: interpreter.alloc(upvalues),
+                    })));
+                }
+                OpCode::CloseUpvalue => {
+                    let top = self.stack.borrow().len() - 1;
+                    self.close_upvalues(top);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    self.close_upvalues(frame.base);
+                    self.stack.borrow_mut().truncate(frame.base);
+                    match callers.pop() {
+                        Some(caller) => {
+                            frame = caller;
+                            interpreter.pop_frame();
+                            self.push(result);
+                        }
+                        None => return Ok(result),
+                    }
+                }
+                OpCode::Class => {
+                    let site = frame.read_u16();
+                    let count = frame.read_byte() as usize;
+                    let inherits = frame.read_byte() == 1;
+                    let name = &frame.site(site).token;
+                    let methods = {
+                        let mut stack = self.stack.borrow_mut();
+                        let start = stack.len() - count;
+                        stack.split_off(start)
+                    };
+                    // the superclass stays on the stack as the local `super`
+                    let superclass = match self.peek(0) {
+                        Typer::Class(superclass) if inherits => Some(superclass),
+                        _ if inherits => {
+                            return Err(error(
+                                name,
+                                ErrorCode::SuperclassNotClass,
+                                "Superclass must be a class.",
+                            ))
+                        }
+                        _ => None,
+                    };
+                    let mut table: HashMap<Symbol, Rc<dyn LoxMethod>> = HashMap::new();
+                    for method in methods {
+                        if let Typer::Callable(method) = method {
+                            if let Some(closure) = method.as_any().downcast_ref::<Closure>() {
+                                table.insert(
+                                    intern(&closure.function.name),
+                                    interpreter.alloc(closure.clone()),
+                                );
+                            }
+                        }
+                    }
+                    let class = LoxClass::new(name.lexeme.to_string(), superclass, table);
+                    self.push(Typer::Class(interpreter.alloc(class)));
+                }
+                OpCode::List => {
+                    let count = frame.read_u16() as usize;
+                    let items = {
+                        let mut stack = self.stack.borrow_mut();
+                        let start = stack.len() - count;
+                        stack.split_off(start)
+                    };
+                    self.push(Typer::List(interpreter.alloc(RefCell::new(items))));
+                }
+                OpCode::Map => {
+                    self.push(Typer::Map(interpreter.alloc(RefCell::new(LoxMap::new()))))
+                }
+                OpCode::MapEntry => {
+                    let site = frame.read_u16();
+                    let colon = &frame.site(site).token;
+                    let value = self.pop();
+                    let key = map_key(&self.pop(), colon)?;
+                    if let Typer::Map(map) = self.peek(0) {
+                        map.borrow_mut().insert(key, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Calls the value under the `count` arguments on top of the stack.
+    /// Compiled functions get a frame for the caller to switch to; anything
+    /// else runs right away and leaves its result on the stack.
+    fn call_value(
+        &self,
+        interpreter: &Interpreter,
+        site: &Site,
+        count: usize,
+    ) -> Result<Option<CallFrame>, Error> {
+        let base = self.stack.borrow().len() - count - 1;
+        let callee = self.stack.borrow()[base].clone();
+        let paren = &site.token;
+        interpreter.check_depth(paren)?;
+        let check_arity = |arity: usize| {
+            if count == arity {
+                return Ok(());
+            }
+            Err(error(
+                paren,
+                ErrorCode::ArityMismatch,
+                format!("Expected {} arguments but got {}.", arity, count),
+            ))
+        };
+
+        match callee {
+            Typer::Callable(function) => {
+                check_arity(function.arity())?;
+                let callable = function.as_any();
+                if let Some(closure) = callable.downcast_ref::<Closure>() {
+                    interpreter.push_frame(function.name(), site.span);
+                    return Ok(Some(CallFrame::new(closure.clone(), base)));
+                }
+                if let Some(bound) = callable.downcast_ref::<BoundMethod>() {
+                    self.stack.borrow_mut()[base] = Typer::Instance(Rc::clone(&bound.receiver));
+                    interpreter.push_frame(function.name(), site.span);
+                    return Ok(Some(CallFrame::new(bound.method.clone(), base)));
+                }
+                let arguments = self.stack.borrow_mut().split_off(base + 1);
+                self.pop();
+                let value = interpreter.in_frame(function.name(), site.span, || {
+                    function
+                        .call(interpreter, arguments)
+                        .map_err(|error| error.or_at(paren))
+                })?;
+                self.push(value);
+                Ok(None)
+            }
+            Typer::Class(class) => {
+                check_arity(class.arity())?;
+                let initializer = class.initializer().cloned();
+                let compiled = initializer
+                    .as_ref()
+                    .and_then(|init| init.as_any().downcast_ref::<Closure>());
+                match (compiled, &initializer) {
+                    (Some(closure), _) => {
+                        let instance = LoxInstance::new(Rc::clone(&class));
+                        let instance = interpreter.alloc(RefCell::new(instance));
+                        self.stack.borrow_mut()[base] = Typer::Instance(instance);
+                        interpreter.push_frame(&class.name, site.span);
+                        Ok(Some(CallFrame::new(closure.clone(), base)))
+                    }
+                    // a class the tree-walker defined knows how to set itself up
+                    (None, Some(_)) => {
+                        let arguments = self.stack.borrow_mut().split_off(base + 1);
+                        self.pop();
+                        let value = interpreter.in_frame(&class.name, site.span, || {
+                            LoxClass::instantiate(&class, interpreter, arguments)
+                        })?;
+                        self.push(value);
+                        Ok(None)
+                    }
+                    (None, None) => {
+                        let instance = LoxInstance::new(Rc::clone(&class));
+                        self.stack.borrow_mut().truncate(base);
+                        self.push(Typer::Instance(interpreter.alloc(RefCell::new(instance))));
+                        Ok(None)
+                    }
+                }
+            }
+            _ => Err(error(
+                paren,
+                ErrorCode::NotCallable,
+                "Can only call functions and classes.",
+            )),
+        }
+    }
+
+    fn push(&self, value: Typer) {
+        self.stack.borrow_mut().push(value);
+    }
+
+    fn pop(&self) -> Typer {
+        self.stack
+            .borrow_mut()
+            .pop()
+            .expect("the stack is not empty")
+    }
+
+    fn peek(&self, distance: usize) -> Typer {
+        let stack = self.stack.borrow();
+        stack[stack.len() - 1 - distance].clone()
+    }
+
+    /// Returns the upvalue for `slot`, sharing it with every closure that
+    /// already captured the same variable.
+    fn capture_upvalue(&self, interpreter: &Interpreter, slot: usize) -> Rc<Upvalue> {
+        let existing = self
+            .open_upvalues
+            .borrow()
+            .iter()
+            .find(|upvalue| matches!(*upvalue.0.borrow(), UpvalueState::Open(open) if open == slot))
+            .cloned();
+        if let Some(existing) = existing {
+            return existing;
+        }
+        let upvalue = Rc::new(Upvalue(RefCell::new(UpvalueState::Open(slot))));
+        self.open_upvalues.borrow_mut().push(Rc::clone(&upvalue));
+        interpreter.track(&upvalue);
+        upvalue
+    }
+
+    /// Adds what the stack and the open upvalues refer to to `roots`.
+    pub(crate) fn roots(&self, roots: &mut Vec<Address>) {
+        if let Ok(stack) = self.stack.try_borrow() {
+            for value in stack.iter() {
+                trace_value(value, &mut |at| roots.push(at));
+            }
+        }
+        if let Ok(open) = self.open_upvalues.try_borrow() {
+            roots.extend(open.iter().map(address));
+        }
+    }
+
+    /// Moves the variables in slots `from` and up off the stack into the
+    /// upvalues that captured them.
+    fn close_upvalues(&self, from: usize) {
+        let stack = self.stack.borrow();
+        self.open_upvalues.borrow_mut().retain(|upvalue| {
+            let mut state = upvalue.0.borrow_mut();
+            match *state {
+                UpvalueState::Open(slot) if slot >= from => {
+                    *state = UpvalueState::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+
+    fn read_upvalue(&self, upvalue: &Upvalue) -> Typer {
+        match &*upvalue.0.borrow() {
+            UpvalueState::Open(slot) => self.stack.borrow()[*slot].clone(),
+            UpvalueState::Closed(value) => value.clone(),
+        }
+    }
+
+    fn write_upvalue(&self, upvalue: &Upvalue, value: Typer) {
+        match &mut *upvalue.0.borrow_mut() {
+            UpvalueState::Open(slot) => self.stack.borrow_mut()[*slot] = value,
+            UpvalueState::Closed(closed) => *closed = value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Backend, Interpreter};
+    use crate::test_support::{global, interpreter_on, run};
+    use crate::typer::Typer;
+
+    #[test]
+    fn upvalues_outlive_their_scope() {
+        let interpreter = interpreter_on(Backend::Bytecode);
+        run(
+            &interpreter,
+            "var get; var set; \
+             { var shared = 1; fun g() { return shared; } fun s(v) { shared = v; } \
+               get = g; set = s; shared = 2; } \
+             var before = get(); set(3); var after = get(); \
+             fun outer() { var x = \"x\"; fun middle() { fun inner() { return x; } return inner; } \
+               return middle(); } \
+             var deep = outer()();",
+        );
+        assert_eq!(global(&interpreter, "before"), "2");
+        assert_eq!(global(&interpreter, "after"), "3");
+        assert_eq!(global(&interpreter, "deep"), "x");
+    }
+
+    #[test]
+    fn calls_cross_backends_and_natives() {
+        let interpreter = Interpreter::new();
+        interpreter.define_native("twice", 1, |interpreter, arguments| match &arguments[0] {
+            Typer::Callable(function) => {
+                function.call(interpreter, Vec::new())?;
+                function.call(interpreter, Vec::new())
+            }
+            _ => Ok(Typer::Nil),
+        });
+        run(
+            &interpreter,
+            "class Counter { init() { this.n = 0; } bump() { this.n = this.n + 1; return this.n; } }",
+        );
+
+        interpreter.set_backend(Backend::Bytecode);
+        run(
+            &interpreter,
+            "var c = Counter(); var n = twice(c.bump); \
+             class Sub < Counter { bump() { return super.bump() * 10; } } \
+             var s = Sub(); var m = twice(s.bump);",
+        );
+        assert_eq!(global(&interpreter, "n"), "2");
+        assert_eq!(global(&interpreter, "m"), "20");
+
+        interpreter.set_backend(Backend::TreeWalk);
+        run(&interpreter, "var again = s.bump();");
+        assert_eq!(global(&interpreter, "again"), "30");
+    }
+}